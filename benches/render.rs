@@ -13,6 +13,8 @@ pub fn render_benchmark(c: &mut Criterion) {
                 form: HashMap::new().tap_mut(|h| {
                     h.insert("name".to_string(), "world".to_string());
                 }),
+                htmx: Default::default(),
+                params: Default::default(),
             }
             .render(context! {
                 greeting => "Hello"
@@ -24,7 +26,9 @@ pub fn render_benchmark(c: &mut Criterion) {
         b.iter(|| {
             Template {
                 path: "/plain".into(),
-                form: HashMap::new(),
+                form: HashMap::<String, String>::new(),
+                htmx: Default::default(),
+                params: Default::default(),
             }
             .render(context!())
         });
@@ -0,0 +1,16 @@
+// Called from hyperscript whenever a todo is added or removed.
+function updateNumDone(): void {
+    const todos = document.getElementById("todos")!;
+    const tasks = todos.children.length - 1;
+    const [title, subtitle] = [document.getElementById("splash-1")!, document.getElementById("splash-2")!];
+
+    if (tasks === 0) {
+        title.innerHTML = "All caught up!";
+        subtitle.innerHTML = "What's on your mind?";
+    } else {
+        title.innerHTML = `${tasks - Number(todos.dataset.numDone)} tasks left`;
+        subtitle.innerHTML = `Tracking ${tasks} tasks`;
+    }
+}
+
+(window as any).updateNumDone = updateNumDone;
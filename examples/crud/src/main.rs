@@ -7,12 +7,11 @@ use axum::response::Html;
 use axum::routing::{delete, get, post};
 use axum::{Extension, Form, Router, Server};
 use chrono::NaiveDateTime;
-use eyre::{ContextCompat, Result};
+use eyre::Result;
 use hyro::prelude::*;
-use hyro::{context, Template};
+use hyro::{context, Template, Validate, Validated, ValidationErrors};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, SqlitePool};
-use tower_http::services::ServeDir;
 
 type Hypermedia = Html<Cow<'static, str>>;
 type Error = (StatusCode, String);
@@ -27,9 +26,40 @@ struct Todo {
     updated: NaiveDateTime,
 }
 
+#[derive(Serialize, Deserialize)]
+struct TodoForm {
+    id: Option<i32>,
+    description: String,
+}
+
+impl Validate for TodoForm {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        if self.description.trim().is_empty() {
+            errors.add("description", "Please describe your todo");
+        }
+    }
+
+    fn template() -> Option<&'static str> {
+        Some("todo-edit")
+    }
+}
+
+#[derive(Deserialize)]
+struct TodoDoneForm {
+    id: i32,
+    done: bool,
+}
+
+#[derive(Deserialize)]
+struct TodoIdForm {
+    id: i32,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     hyro::config::set_template_file_extension("html.j2").unwrap();
+    // Todos are rendered in place on the index, which goes through a component instead of `todo`.
+    hyro::component("todo", |_: HashMap<String, String>| context!());
 
     let db_path = std::env::temp_dir().join("hyro.db");
     if !db_path.exists() {
@@ -47,7 +77,8 @@ async fn main() -> Result<()> {
         .route("/todo", delete(delete_todo))
         .route("/todo-done", get(todo_done))
         .route("/todo-edit", get(todo_edit))
-        .nest_service("/assets", ServeDir::new("assets"))
+        .with_bundled_js("/todos.js", "scripts/todos.ts")
+        .with_assets("/assets", "assets")
         .layer(Extension(pool))
         .into_service_with_hmr();
 
@@ -81,20 +112,11 @@ async fn todo_edit(template: Template) -> Hypermedia {
 
 async fn todo_done(
     Extension(pool): Extension<SqlitePool>,
-    Form(form): Form<HashMap<String, String>>,
+    Form(form): Form<TodoDoneForm>,
 ) -> Result<(), Error> {
     sqlx::query("UPDATE todos SET done = $1 WHERE id = $2")
-        .bind(
-            form.get("done")
-                .context("please provide done")
-                .map_err(bad_request)?
-                == "false",
-        )
-        .bind(
-            form.get("id")
-                .context("please provide an id")
-                .map_err(bad_request)?,
-        )
+        .bind(!form.done)
+        .bind(form.id)
         .execute(&pool)
         .await
         .map_err(internal_error)?;
@@ -104,21 +126,19 @@ async fn todo_done(
 
 async fn create_todo(
     Extension(pool): Extension<SqlitePool>,
-    template: Template,
+    Validated(template): Validated<TodoForm>,
 ) -> MaybeHypermedia {
-    let id = if let (Some(description), Some(local_id)) =
-        (template.form.get("description"), template.form.get("id"))
-    {
+    let id = if let Some(local_id) = template.form.id {
         sqlx::query("UPDATE todos SET description = $1 WHERE id = $2")
-            .bind(description)
+            .bind(&template.form.description)
             .bind(local_id)
             .execute(&pool)
             .await
             .map_err(internal_error)?;
-        local_id.parse().map_err(internal_error)?
+        local_id.into()
     } else {
         sqlx::query("INSERT INTO todos (description) VALUES ($1) RETURNING id")
-            .bind(template.form.get("description").unwrap())
+            .bind(&template.form.description)
             .execute(&pool)
             .await
             .map_err(internal_error)?
@@ -136,14 +156,10 @@ async fn create_todo(
 
 async fn delete_todo(
     Extension(pool): Extension<SqlitePool>,
-    Form(form): Form<HashMap<String, String>>,
+    Form(form): Form<TodoIdForm>,
 ) -> Result<(), Error> {
     sqlx::query("DELETE FROM todos WHERE id = $1")
-        .bind(
-            form.get("id")
-                .context("please provide an id")
-                .map_err(bad_request)?,
-        )
+        .bind(form.id)
         .execute(&pool)
         .await
         .map_err(internal_error)?;
@@ -151,10 +167,6 @@ async fn delete_todo(
     Ok(())
 }
 
-fn bad_request(err: eyre::Error) -> (StatusCode, String) {
-    (StatusCode::BAD_REQUEST, err.to_string())
-}
-
 fn internal_error<E: std::error::Error>(err: E) -> (StatusCode, String) {
     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 }
@@ -0,0 +1,181 @@
+#![forbid(unsafe_code)]
+
+use std::path::{Path, PathBuf};
+
+use lightningcss::bundler::{Bundler, FileProvider, SourceProvider};
+use lightningcss::stylesheet::{ParserFlags, ParserOptions, PrinterOptions};
+use lightningcss::targets::{Browsers, Targets};
+use parking_lot::Mutex;
+use proc_macro::{TokenStream, TokenTree};
+
+/// Bakes directories (relative to the crate root) into release builds, so that templates,
+/// stylesheets and assets are read from the binary instead of the file system.
+///
+/// Call this at the start of `main`, before rendering anything or bundling CSS. Paths passed to
+/// `config::set_template_dir` and `with_bundled_css` resolve to the embedded files, and debug builds
+/// keep reading from the file system so that HMR still works.
+///
+/// Stylesheets named with `css = ".."` are bundled at compile time, so that `with_bundled_css`
+/// serves them without bundling them at startup. Bundling errors fail the build.
+///
+/// ```ignore
+/// fn main() {
+///     hyro::embed!("templates", "style", css = "style/main.css");
+///     // ...
+/// }
+/// ```
+///
+/// Files that weren't embedded are still read from the file system. Calling `embed!` more than
+/// once panics.
+///
+/// Cargo won't rebuild when a file is *added* to an embedded directory. Editing existing files
+/// works as expected.
+#[proc_macro]
+pub fn embed(input: TokenStream) -> TokenStream {
+    let root = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let mut files = String::new();
+    let mut bundles = String::new();
+    let mut tokens = input.into_iter();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Literal(lit) => {
+                let Some(dir) = string_literal(&lit.to_string()) else {
+                    return compile_error("expected a string literal");
+                };
+
+                match embed_dir(&root, &dir) {
+                    Ok(embedded) => files += &embedded,
+                    Err(e) => return compile_error(&e),
+                }
+            }
+            TokenTree::Ident(ident) if ident.to_string() == "css" => {
+                let path = match (tokens.next(), tokens.next()) {
+                    (Some(TokenTree::Punct(p)), Some(TokenTree::Literal(lit)))
+                        if p.as_char() == '=' =>
+                    {
+                        string_literal(&lit.to_string())
+                    }
+                    _ => None,
+                };
+                let Some(path) = path else {
+                    return compile_error("expected `css = \"path/to/main.css\"`");
+                };
+
+                match bundle_css(&root, &path) {
+                    Ok(bundle) => bundles += &bundle,
+                    Err(e) => return compile_error(&e),
+                }
+            }
+            TokenTree::Punct(p) if p.as_char() == ',' => continue,
+            _ => return compile_error("expected a comma-separated list of directories"),
+        }
+    }
+
+    format!("{{ #[cfg(not(debug_assertions))] ::hyro::_embed(&[{files}], &[{bundles}]); }}")
+        .parse()
+        .unwrap()
+}
+
+fn string_literal(s: &str) -> Option<String> {
+    (s.len() > 1 && s.starts_with('"') && s.ends_with('"')).then(|| s[1..s.len() - 1].to_string())
+}
+
+/// Every file inside of `dir`, as a `(path, content)` tuple.
+fn embed_dir(root: &Path, dir: &str) -> Result<String, String> {
+    let dir_path = root.join(dir);
+    if !dir_path.is_dir() {
+        return Err(format!("{} is not a directory", dir_path.display()));
+    }
+
+    let mut files = String::new();
+    for entry in walkdir::WalkDir::new(&dir_path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|i| i.path().is_file())
+    {
+        let relative = entry.path().strip_prefix(root).unwrap();
+        files += &format!(
+            "({:?}, include_bytes!({:?}) as &[u8]),",
+            relative.display().to_string(),
+            entry.path().display().to_string()
+        );
+    }
+
+    Ok(files)
+}
+
+/// Reads stylesheets like lightningcss' `FileProvider`, remembering every file that was read.
+struct TrackedProvider {
+    files: FileProvider,
+    read: Mutex<Vec<PathBuf>>,
+}
+
+impl SourceProvider for TrackedProvider {
+    type Error = std::io::Error;
+
+    fn read<'a>(&'a self, file: &Path) -> Result<&'a str, Self::Error> {
+        self.read.lock().push(file.to_path_buf());
+        self.files.read(file)
+    }
+
+    fn resolve(&self, specifier: &str, originating_file: &Path) -> Result<PathBuf, Self::Error> {
+        self.files.resolve(specifier, originating_file)
+    }
+}
+
+/// Bundles a stylesheet with hyro's default style options, as a `(path, css)` tuple.
+fn bundle_css(root: &Path, path: &str) -> Result<String, String> {
+    let provider = TrackedProvider {
+        files: FileProvider::new(),
+        read: Mutex::default(),
+    };
+    let options = ParserOptions {
+        flags: ParserFlags::NESTING | ParserFlags::CUSTOM_MEDIA,
+        ..Default::default()
+    };
+
+    let mut bundler = Bundler::new(&provider, None, options);
+    let css = bundler
+        .bundle(&root.join(path))
+        .map_err(|e| format!("Error while bundling {path}: {e}"))?
+        .to_css(PrinterOptions {
+            minify: true,
+            targets: default_targets(),
+            ..Default::default()
+        })
+        .map_err(|e| format!("Error while bundling {path}: {e}"))?
+        .code;
+
+    // Reading every imported stylesheet with `include_bytes!` rebuilds the bundle when one changes.
+    let tracked: String = provider
+        .read
+        .lock()
+        .iter()
+        .map(|file| format!("include_bytes!({:?});", file.display().to_string()))
+        .collect();
+
+    Ok(format!("({path:?}, {{ {tracked} {css:?} }}),"))
+}
+
+/// The same browsers that hyro targets when `config::set_style_options` isn't called.
+fn default_targets() -> Targets {
+    Targets {
+        browsers: Some(Browsers {
+            android: None,
+            chrome: Some(6160384),
+            edge: Some(6291456),
+            firefox: Some(6160384),
+            ie: Some(720896),
+            ios_saf: Some(786944),
+            opera: Some(5308416),
+            safari: Some(852224),
+            samsung: None,
+        }),
+        ..Default::default()
+    }
+}
+
+fn compile_error(message: &str) -> TokenStream {
+    format!("compile_error!({message:?})").parse().unwrap()
+}
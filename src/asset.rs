@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::path::{Component, Path};
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+/// Fingerprinted assets are never requested again once a browser has them.
+#[cfg(not(debug_assertions))]
+pub(crate) const CACHE_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// Assets at their plain URL are checked against their ETag on every request.
+pub(crate) const CACHE_REVALIDATE: &str = "no-cache";
+
+/// The fingerprinted URL of every asset, keyed by its plain URL.
+static ASSET_URLS: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(Default::default);
+
+/// A hash of an asset's content, which changes whenever the asset does. It stays the same across
+/// builds and Rust versions, so fingerprinted URLs cached by browsers and CDNs survive a deploy.
+pub(crate) fn hash_of(content: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(content)[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+pub(crate) fn etag_of(hash: &str) -> String {
+    format!("\"{hash}\"")
+}
+
+/// Whether the client's copy, identified by its `If-None-Match` header, is still current.
+pub(crate) fn is_fresh(if_none_match: Option<&str>, etag: &str) -> bool {
+    if_none_match.is_some_and(|tags| {
+        tags.split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == etag || tag == "*")
+    })
+}
+
+/// Registers an asset's content hash, returning the fingerprinted URL it's served at.
+/// `/main.css` becomes `/main.0123456789abcdef.css`.
+#[cfg(not(debug_assertions))]
+pub(crate) fn fingerprint(url: &str, hash: &str) -> String {
+    let fingerprinted = fingerprinted_url(url, hash);
+    ASSET_URLS
+        .write()
+        .insert(url.to_string(), fingerprinted.clone());
+    fingerprinted
+}
+
+/// Inserts the hash before the file's last extension, so that `app.min.js` keeps its `.js`
+/// extension as `app.min.<hash>.js`. The leading dot of a dotfile isn't an extension.
+#[cfg(not(debug_assertions))]
+fn fingerprinted_url(url: &str, hash: &str) -> String {
+    let (dir, file) = url.rsplit_once('/').unwrap_or(("", url));
+    match file.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{dir}/{stem}.{hash}.{extension}"),
+        _ => format!("{dir}/{file}.{hash}"),
+    }
+}
+
+/// The `asset_url` template function, which resolves an asset to its fingerprinted URL,
+/// e.g. `<link rel="stylesheet" href="{{ asset_url("/main.css") }}">`. Debug builds serve
+/// assets at their plain URL, so that they can be reloaded by HMR.
+pub(crate) fn asset_url(url: String) -> String {
+    ASSET_URLS.read().get(&url).cloned().unwrap_or(url)
+}
+
+/// A file served by `with_assets`.
+pub(crate) struct Asset {
+    pub(crate) content_type: String,
+    pub(crate) etag: String,
+    body: Vec<u8>,
+    /// Precompressed copies of the body, kept only when they're smaller than the original.
+    gzip: Option<Vec<u8>>,
+    brotli: Option<Vec<u8>>,
+}
+
+impl Asset {
+    /// Reads an asset, relative to the directory it's served from. Paths that would escape the
+    /// directory aren't served.
+    pub(crate) fn read(dir: &Path, name: &str) -> Option<Self> {
+        if !Path::new(name)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+
+        let path = dir.join(name);
+        let body = match crate::embedded::get_bytes(&path) {
+            Some(content) => content.to_vec(),
+            None => std::fs::read(&path).ok()?,
+        };
+
+        Some(Self {
+            content_type: mime_guess::from_path(&path)
+                .first_or_octet_stream()
+                .to_string(),
+            etag: etag_of(&hash_of(&body)),
+            body,
+            gzip: None,
+            brotli: None,
+        })
+    }
+
+    /// Compresses the asset ahead of time, so that it's compressed only once.
+    #[cfg(not(debug_assertions))]
+    pub(crate) fn precompress(mut self) -> Self {
+        use std::io::Write;
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        self.gzip = gzip
+            .write_all(&self.body)
+            .and_then(|_| gzip.finish())
+            .ok()
+            .filter(|gzip| gzip.len() < self.body.len());
+
+        let mut brotli = Vec::new();
+        let params = brotli::enc::BrotliEncoderParams::default();
+        self.brotli = brotli::BrotliCompress(&mut &self.body[..], &mut brotli, &params)
+            .ok()
+            .map(|_| brotli)
+            .filter(|brotli| brotli.len() < self.body.len());
+
+        self
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub(crate) fn hash(&self) -> &str {
+        self.etag.trim_matches('"')
+    }
+
+    /// The body best suited to the client's `Accept-Encoding`, along with its `Content-Encoding`.
+    pub(crate) fn encoded(&self, accept_encoding: Option<&str>) -> (&[u8], Option<&'static str>) {
+        let accepts = |coding: &str| {
+            accept_encoding.is_some_and(|codings| {
+                codings.split(',').any(|c| {
+                    let mut parts = c.split(';').map(str::trim);
+                    parts.next() == Some(coding) && parts.all(|p| p != "q=0")
+                })
+            })
+        };
+
+        match (&self.brotli, &self.gzip) {
+            (Some(brotli), _) if accepts("br") => (brotli, Some("br")),
+            (_, Some(gzip)) if accepts("gzip") => (gzip, Some("gzip")),
+            _ => (&self.body, None),
+        }
+    }
+}
+
+/// Every asset inside of `dir`, read and precompressed, along with the URL it's served at.
+#[cfg(not(debug_assertions))]
+pub(crate) fn assets_in(endpoint: &str, dir: &Path) -> Vec<(String, Asset)> {
+    crate::embedded::list(dir)
+        .into_iter()
+        .filter_map(|name| {
+            let asset = Asset::read(dir, &name)?.precompress();
+            Some((format!("{}/{name}", endpoint.trim_end_matches('/')), asset))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(debug_assertions))]
+    #[test]
+    fn fingerprints_go_before_the_last_extension() {
+        assert_eq!(fingerprinted_url("/main.css", "abc"), "/main.abc.css");
+        assert_eq!(
+            fingerprinted_url("/assets/app.min.js", "abc"),
+            "/assets/app.min.abc.js"
+        );
+        assert_eq!(fingerprinted_url("/LICENSE", "abc"), "/LICENSE.abc");
+        assert_eq!(fingerprinted_url("/.env", "abc"), "/.env.abc");
+        assert_eq!(fingerprinted_url("/.env.local", "abc"), "/.env.abc.local");
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[test]
+    fn asset_urls_resolve_to_their_fingerprint() {
+        let fingerprinted = fingerprint("/fingerprinted.min.js", "abc");
+        assert_eq!(fingerprinted, "/fingerprinted.min.abc.js");
+        assert_eq!(
+            asset_url("/fingerprinted.min.js".to_string()),
+            fingerprinted
+        );
+        assert_eq!(asset_url("/unknown.js".to_string()), "/unknown.js");
+    }
+
+    #[test]
+    fn etags_match_if_none_match() {
+        let etag = etag_of(&hash_of(b"body"));
+        assert!(is_fresh(Some(&etag), &etag));
+        assert!(is_fresh(Some(&format!("\"other\", W/{etag}")), &etag));
+        assert!(is_fresh(Some("*"), &etag));
+        assert!(!is_fresh(Some("\"other\""), &etag));
+        assert!(!is_fresh(None, &etag));
+    }
+
+    #[test]
+    fn assets_are_encoded_as_the_client_accepts() {
+        let asset = Asset {
+            content_type: "text/plain".to_string(),
+            etag: etag_of(&hash_of(b"body")),
+            body: b"body".to_vec(),
+            gzip: Some(b"gzip".to_vec()),
+            brotli: Some(b"br".to_vec()),
+        };
+
+        assert_eq!(asset.encoded(None), (&b"body"[..], None));
+        assert_eq!(asset.encoded(Some("gzip")), (&b"gzip"[..], Some("gzip")));
+        assert_eq!(asset.encoded(Some("gzip, br")), (&b"br"[..], Some("br")));
+        assert_eq!(
+            asset.encoded(Some("gzip, br;q=0")),
+            (&b"gzip"[..], Some("gzip"))
+        );
+    }
+}
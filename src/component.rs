@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use minijinja::value::{Kwargs, Value};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::path_of_endpoint;
+
+type Component = Arc<dyn Fn(Props) -> Result<Value, String> + Send + Sync>;
+
+/// Components keyed by the name of their template, relative to the template directory.
+static COMPONENTS: Lazy<RwLock<HashMap<String, Component>>> = Lazy::new(Default::default);
+
+/// Props come from keyword arguments when a component is called from a template, and from
+/// a query string when it's loaded as a module.
+pub(crate) enum Props {
+    Value(Value),
+    Form(HashMap<String, String>),
+}
+
+/// What a component's function can return: a context, or a result of one.
+pub trait ComponentContext {
+    fn into_context(self) -> Result<Value, String>;
+}
+
+impl ComponentContext for Value {
+    fn into_context(self) -> Result<Value, String> {
+        Ok(self)
+    }
+}
+
+impl<E: std::fmt::Display> ComponentContext for Result<Value, E> {
+    fn into_context(self) -> Result<Value, String> {
+        self.map_err(|e| e.to_string())
+    }
+}
+
+/// Registers a component, pairing the template named `name` in the template directory with a
+/// function that turns its props into the template's context.
+///
+/// Components are rendered in place with `{{ component("todo", id=3) }}`, where the keyword
+/// arguments are deserialized into the function's props. Inline modules of the same name,
+/// like `{{ module("todo", {"id": 3}, inline=true) }}`, also render through the component.
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// struct TodoProps {
+///     id: i64,
+/// }
+///
+/// hyro::component("todo", |props: TodoProps| {
+///     let todo = TODOS.read().get(&props.id).cloned().ok_or("no such todo")?;
+///     Ok::<_, &str>(context!(todo))
+/// });
+/// ```
+///
+/// The function runs while the page is rendering, so it can't wait on anything. Data that has to
+/// be loaded asynchronously belongs in a module, which is loaded with a request of its own.
+pub fn component<P, F, C>(name: &str, function: F)
+where
+    P: DeserializeOwned,
+    F: Fn(P) -> C + Send + Sync + 'static,
+    C: ComponentContext,
+{
+    let component: Component = Arc::new(move |props| {
+        let props = match props {
+            Props::Value(value) => P::deserialize(value).map_err(|e| e.to_string())?,
+            Props::Form(form) => {
+                let encoded = serde_urlencoded::to_string(form).unwrap_or_default();
+                serde_urlencoded::from_str(&encoded).map_err(|e| e.to_string())?
+            }
+        };
+
+        function(props).into_context()
+    });
+
+    COMPONENTS.write().insert(path_of_endpoint(name), component);
+}
+
+/// Whether a component is registered for the template.
+pub(crate) fn exists(template_name: &str) -> bool {
+    COMPONENTS.read().contains_key(template_name)
+}
+
+/// Runs the component registered for the template, if there is one, returning its context.
+///
+/// Renders hold a snapshot of the environment rather than its lock (see
+/// [render::environment](crate::render::environment)), so a component may render templates of its
+/// own without waiting on HMR or `modify_template_env`, which would wait on the render in turn.
+pub(crate) fn context_of(
+    template_name: &str,
+    props: Props,
+) -> Option<Result<Value, minijinja::Error>> {
+    let component = COMPONENTS.read().get(template_name).cloned()?;
+
+    let context = component(props).map_err(|e| {
+        minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("component {template_name} failed: {e}"),
+        )
+    });
+
+    Some(context)
+}
+
+/// The `component` template function. Props can be passed as keyword arguments, or as a map,
+/// and are available to the template as `props`, and as `form` like they would be in a module.
+pub(crate) fn render_component(
+    state: &minijinja::State,
+    name: String,
+    props: Option<Value>,
+    kwargs: Kwargs,
+) -> Result<Value, minijinja::Error> {
+    let mut all_props: BTreeMap<String, Value> = match props {
+        Some(props) => BTreeMap::deserialize(props)?,
+        None => BTreeMap::new(),
+    };
+    for key in kwargs.args() {
+        all_props.insert(key.to_string(), kwargs.get(key)?);
+    }
+
+    let template_name = path_of_endpoint(&name);
+    let props = Value::from(all_props);
+    let context = context_of(&template_name, Props::Value(props.clone())).unwrap_or_else(|| {
+        Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("no component named {name}"),
+        ))
+    })?;
+
+    state
+        .env()
+        .get_template(&template_name)?
+        .render(minijinja::context! {
+            form => props.clone(),
+            props,
+            htmx => Value::from_serializable(&crate::Htmx::default()),
+            ..context
+        })
+        .map(Value::from_safe_string)
+}
@@ -1,10 +1,9 @@
-use parking_lot::MutexGuard;
 use std::path::{Path, PathBuf};
 
 use lightningcss::stylesheet::ParserOptions;
 use lightningcss::targets::Targets;
 
-use crate::render::ENVIRONMENT;
+use crate::render::modify_environment;
 use crate::{TEMPLATE_DIR, TEMPLATE_EXTENSION};
 
 pub fn set_template_dir<T: AsRef<Path>>(dir: T) -> Result<(), PathBuf> {
@@ -23,6 +22,12 @@ pub fn set_template_file_extension<S: AsRef<str>>(extension: S) -> Result<(), St
     TEMPLATE_EXTENSION.set(format!(".{}", extension))
 }
 
+/// Renders `module()` calls that have a component in place by default, instead of loading them with
+/// htmx once the page has loaded. Individual modules can opt out with `inline=false`.
+pub fn set_inline_modules(inline: bool) -> Result<(), bool> {
+    crate::render::INLINE_MODULES.set(inline)
+}
+
 pub fn set_style_options(options: ParserOptions<'static, 'static>) {
     crate::style::STYLE_OPTIONS.set(options).unwrap();
 }
@@ -31,8 +36,6 @@ pub fn set_style_targets(targets: Targets) {
     crate::style::STYLE_TARGETS.set(targets).unwrap();
 }
 
-pub fn modify_template_env<F: FnOnce(&mut MutexGuard<'_, minijinja::Environment<'static>>)>(
-    func: F,
-) {
-    func(&mut ENVIRONMENT.lock());
+pub fn modify_template_env<F: FnOnce(&mut minijinja::Environment<'static>)>(func: F) {
+    modify_environment(func);
 }
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use once_cell::sync::OnceCell;
+
+/// Files baked into the binary with `hyro::embed!`, keyed by their path relative to the crate root.
+static EMBEDDED: OnceCell<HashMap<PathBuf, &'static [u8]>> = OnceCell::new();
+
+/// Stylesheets bundled at compile time by `hyro::embed!`, keyed by their entrypoint.
+static BUNDLED: OnceCell<HashMap<PathBuf, &'static str>> = OnceCell::new();
+
+pub(crate) fn set(
+    files: &'static [(&'static str, &'static [u8])],
+    bundles: &'static [(&'static str, &'static str)],
+) {
+    let files = files
+        .iter()
+        .map(|(path, content)| (normalize(Path::new(path)), *content))
+        .collect();
+    let bundles = bundles
+        .iter()
+        .map(|(path, css)| (normalize(Path::new(path)), *css))
+        .collect();
+
+    if EMBEDDED.set(files).is_err() || BUNDLED.set(bundles).is_err() {
+        panic!("hyro::embed! can only be called once");
+    }
+}
+
+/// The content of an embedded file, like an image or a font.
+pub(crate) fn get_bytes<P: AsRef<Path>>(path: P) -> Option<&'static [u8]> {
+    EMBEDDED.get()?.get(&normalize(path.as_ref())).copied()
+}
+
+/// The source of an embedded text file, like a template or a stylesheet.
+pub(crate) fn get<P: AsRef<Path>>(path: P) -> Option<&'static str> {
+    let bytes = get_bytes(&path)?;
+    std::str::from_utf8(bytes)
+        .map_err(|_| error!("{} isn't UTF-8", path.as_ref().display()))
+        .ok()
+}
+
+/// The stylesheet at `path`, as bundled at compile time.
+#[cfg(not(debug_assertions))]
+pub(crate) fn bundled<P: AsRef<Path>>(path: P) -> Option<&'static str> {
+    BUNDLED.get()?.get(&normalize(path.as_ref())).copied()
+}
+
+/// Lists every embedded file inside of `dir`, relative to `dir`.
+/// Returns `None` when nothing was embedded.
+#[cfg(not(debug_assertions))]
+fn files_in<P: AsRef<Path>>(dir: P) -> Option<Vec<PathBuf>> {
+    let dir = normalize(dir.as_ref());
+
+    Some(
+        EMBEDDED
+            .get()?
+            .keys()
+            .filter_map(|path| Some(path.strip_prefix(&dir).ok()?.into()))
+            .collect(),
+    )
+}
+
+/// Lists the files inside of `dir`, relative to `dir` and sorted. Embedded files are listed along
+/// with the ones on the file system, so that files left out of `hyro::embed!` are still found.
+pub(crate) fn list<P: AsRef<Path>>(dir: P) -> Vec<String> {
+    let dir = dir.as_ref();
+
+    let on_disk = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|i| i.path().is_file())
+        .filter_map(|entry| Some(entry.path().strip_prefix(dir).ok()?.to_path_buf()));
+
+    #[cfg(not(debug_assertions))]
+    let on_disk = on_disk.chain(files_in(dir).unwrap_or_default());
+
+    let mut files: Vec<String> = on_disk
+        .map(|name| name.to_string_lossy().replace('\\', "/"))
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Embedded paths can't be canonicalized, so `.` and `..` are resolved by hand.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,150 @@
+use std::fmt;
+
+use minijinja::ErrorKind;
+
+/// An error that occurred while rendering a template.
+///
+/// With `framework-axum`, this can be returned from a handler directly. With `framework-trillium`,
+/// it can be run as a handler on the current `Conn`.
+#[derive(Debug)]
+pub enum Error {
+    /// No template exists for the requested endpoint.
+    MissingTemplate(String),
+    /// The template (or one of the templates it references) failed to parse.
+    Syntax(minijinja::Error),
+    /// The template used a variable that wasn't provided, with a strict undefined behavior.
+    UndefinedVariable(minijinja::Error),
+    /// The context couldn't be converted into template values.
+    Serialization(minijinja::Error),
+    /// Any other error raised by the template while rendering.
+    Render(minijinja::Error),
+    /// The submitted form couldn't be deserialized into the handler's form type.
+    InvalidForm {
+        field: Option<String>,
+        message: String,
+    },
+}
+
+impl Error {
+    /// The HTTP status code this error should be served with.
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::MissingTemplate(_) => 404,
+            Self::InvalidForm { .. } => 422,
+            _ => 500,
+        }
+    }
+
+    /// Debug builds serve the full error, while release builds only serve the status text.
+    /// Form errors are always served in full, since they're caused by the client.
+    pub(crate) fn body(&self) -> String {
+        if cfg!(debug_assertions) || self.status() == 422 {
+            self.to_string()
+        } else {
+            match self.status() {
+                404 => "Not Found".into(),
+                _ => "Internal Server Error".into(),
+            }
+        }
+    }
+}
+
+impl From<minijinja::Error> for Error {
+    fn from(e: minijinja::Error) -> Self {
+        match e.kind() {
+            ErrorKind::SyntaxError => Self::Syntax(e),
+            ErrorKind::UndefinedError => Self::UndefinedVariable(e),
+            ErrorKind::BadSerialization => Self::Serialization(e),
+            _ => Self::Render(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingTemplate(endpoint) => write!(f, "no template found for {endpoint}"),
+            Self::InvalidForm {
+                field: Some(field),
+                message,
+            } => write!(f, "invalid form field `{field}`: {message}"),
+            Self::InvalidForm {
+                field: None,
+                message,
+            } => write!(f, "invalid form: {message}"),
+            Self::Syntax(e)
+            | Self::UndefinedVariable(e)
+            | Self::Serialization(e)
+            | Self::Render(e) => {
+                write!(f, "{e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::MissingTemplate(_) | Self::InvalidForm { .. } => None,
+            Self::Syntax(e)
+            | Self::UndefinedVariable(e)
+            | Self::Serialization(e)
+            | Self::Render(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_templates_are_not_found() {
+        let e = Error::MissingTemplate("/users/".into());
+        assert_eq!(e.status(), 404);
+        if cfg!(debug_assertions) {
+            assert_eq!(e.body(), "no template found for /users/");
+        } else {
+            assert_eq!(e.body(), "Not Found");
+        }
+    }
+
+    #[test]
+    fn render_errors_are_internal() {
+        let e = Error::from(minijinja::Error::new(
+            ErrorKind::InvalidOperation,
+            "something broke",
+        ));
+        assert!(matches!(e, Error::Render(_)));
+        assert_eq!(e.status(), 500);
+        if cfg!(debug_assertions) {
+            assert!(e.body().contains("something broke"));
+        } else {
+            assert_eq!(e.body(), "Internal Server Error");
+        }
+
+        let e = Error::from(minijinja::Error::new(ErrorKind::SyntaxError, "unexpected"));
+        assert!(matches!(e, Error::Syntax(_)));
+        assert_eq!(e.status(), 500);
+    }
+
+    #[test]
+    fn invalid_forms_are_always_served_in_full() {
+        let e = Error::InvalidForm {
+            field: Some("age".into()),
+            message: "invalid digit found in string".into(),
+        };
+        assert_eq!(e.status(), 422);
+        assert_eq!(
+            e.body(),
+            "invalid form field `age`: invalid digit found in string"
+        );
+
+        let e = Error::InvalidForm {
+            field: None,
+            message: "missing field `name`".into(),
+        };
+        assert_eq!(e.status(), 422);
+        assert_eq!(e.body(), "invalid form: missing field `name`");
+    }
+}
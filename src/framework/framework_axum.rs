@@ -14,30 +14,53 @@ pub fn into_rendered_template(cow: Cow<'static, str>) -> RenderedTemplate {
     Html(cow)
 }
 
+pub fn from_rendered_template(rendered: RenderedTemplate) -> Cow<'static, str> {
+    rendered.0
+}
+
+/// A template that's rendered while it's being sent. See [Template::render_stream](crate::Template::render_stream).
+pub struct StreamedTemplate(crate::runtime::MpscReceiver<String>);
+
+pub fn into_streamed_template(chunks: crate::runtime::MpscReceiver<String>) -> StreamedTemplate {
+    StreamedTemplate(chunks)
+}
+
+impl IntoResponse for StreamedTemplate {
+    fn into_response(self) -> axum::response::Response {
+        use crate::runtime::StreamExt;
+
+        (
+            [(axum::http::header::CONTENT_TYPE, "text/html; charset=utf-8")],
+            axum::body::StreamBody::new(self.0.map(Ok::<_, std::convert::Infallible>)),
+        )
+            .into_response()
+    }
+}
+
 #[cfg(debug_assertions)]
 pub async fn hmr_websocket(
-    axum::extract::ConnectInfo(ip): axum::extract::ConnectInfo<SocketAddr>,
+    axum::extract::RawQuery(query): axum::extract::RawQuery,
     ws: axum::extract::WebSocketUpgrade,
 ) -> axum::response::Response {
-    let ip = ip.ip();
-    ws.on_upgrade(move |socket| async move { crate::hmr::hmr_handler(socket, ip).await })
+    let client = crate::hmr::client_of_query(query.as_deref().unwrap_or_default());
+    ws.on_upgrade(move |socket| async move { crate::hmr::hmr_handler(socket, client).await })
 }
 
 #[cfg(debug_assertions)]
+pub async fn hmr_state() -> axum::response::Response {
+    (
+        [(axum::http::header::CONTENT_TYPE, "application/json")],
+        crate::hmr::state_json(),
+    )
+        .into_response()
+}
+
 pub async fn websocket_recv(
     socket: &mut WebSocket,
 ) -> Option<Result<axum::extract::ws::Message, axum::Error>> {
     socket.recv().await
 }
 
-#[cfg(debug_assertions)]
-pub async fn websocket_unwrap<T: std::fmt::Debug>(
-    socket: Result<usize, tokio::sync::broadcast::error::SendError<T>>,
-) {
-    socket.unwrap();
-}
-
-#[cfg(debug_assertions)]
 pub fn websocket_message_text(text: String) -> axum::extract::ws::Message {
     axum::extract::ws::Message::Text(text)
 }
@@ -47,7 +70,6 @@ pub fn websocket_message_binary(data: Vec<u8>) -> axum::extract::ws::Message {
     axum::extract::ws::Message::Binary(data)
 }
 
-#[cfg(debug_assertions)]
 pub fn websocket_try_as_text(message: axum::extract::ws::Message) -> Option<String> {
     match message {
         axum::extract::ws::Message::Text(t) => Some(t),
@@ -72,7 +94,37 @@ pub trait RouterExt<S, C> {
     ) -> axum::extract::connect_info::IntoMakeServiceWithConnectInfo<S, C>;
     #[cfg(not(debug_assertions))]
     fn into_service_with_hmr(self) -> axum::routing::IntoMakeService<Router>;
+    /// Bundles a stylesheet with everything it `@import`s, serving it at `endpoint`.
+    /// Can be called once for every stylesheet, like `/admin.css` and `/public.css`.
     fn with_bundled_css<P: AsRef<Path>>(self, endpoint: &str, main_css_path: P) -> Self;
+    /// Serves every stylesheet in `dir` as a CSS module at `endpoint`. Templates get the scoped
+    /// class names of a module with `css_module`, e.g. `css_module("card.css").title`.
+    fn with_css_modules<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self;
+    /// Bundles a JavaScript or TypeScript module with everything it imports, serving it at
+    /// `endpoint`. Only relative imports are bundled. Bundling errors are logged, and served
+    /// with a 500 in place of the script. Needs the `bundle-js` feature.
+    #[cfg(feature = "bundle-js")]
+    fn with_bundled_js<P: AsRef<Path>>(self, endpoint: &str, entry: P) -> Self;
+    /// Serves every file in `dir` under `endpoint`, like `/assets/logo.png`. Release builds
+    /// precompress each file, and also serve it at a fingerprinted URL, resolved with `asset_url`.
+    fn with_assets<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self;
+    /// Serves a stream of server-sent events at `endpoint`, which sends every template published
+    /// to it with [publish](crate::publish) to each connected client.
+    fn with_event_stream(self, endpoint: &str) -> Self;
+    /// Serves a websocket at `endpoint` for htmx's `ws` extension. Each form sent with `ws-send` is
+    /// passed to `handler`, and whatever it renders is sent back, usually with [render_oob](crate::render_oob).
+    /// Fragments can also be pushed to a connection at any time, through [Socket](crate::Socket) or
+    /// [broadcast](crate::broadcast).
+    fn with_websocket<H, Fut>(self, endpoint: &str, handler: H) -> Self
+    where
+        H: Fn(crate::SocketMessage) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<String, crate::Error>> + Send + 'static;
+    /// Serves every template in the template directory at its own route, for requests that no
+    /// other route matches. `users/[id].html.jinja2` is served at `/users/:id`.
+    ///
+    /// Templates are served through the router's fallback, so this panics if the router already
+    /// has one, and a fallback set afterwards replaces the template routes.
+    fn with_template_routes(self) -> Self;
 }
 
 impl RouterExt<Router, SocketAddr> for axum::Router {
@@ -82,105 +134,513 @@ impl RouterExt<Router, SocketAddr> for axum::Router {
     ) -> axum::extract::connect_info::IntoMakeServiceWithConnectInfo<Router, SocketAddr> {
         crate::hmr::watch_templates();
         self.route("/hmr", axum::routing::get(hmr_websocket))
+            .route("/hmr/state", axum::routing::get(hmr_state))
             .layer(tower_http::trace::TraceLayer::new_for_http())
             .into_make_service_with_connect_info::<SocketAddr>()
     }
     #[cfg(not(debug_assertions))]
     fn into_service_with_hmr(self) -> axum::routing::IntoMakeService<Router> {
+        once_cell::sync::Lazy::force(&crate::render::ENVIRONMENT);
         self.into_make_service()
     }
 
-    #[cfg(debug_assertions)]
     fn with_bundled_css<P: AsRef<Path>>(self, endpoint: &str, main_css_path: P) -> Self {
-        crate::style::STYLE_MAIN_FILE
-            .set(main_css_path.as_ref().to_path_buf())
-            .unwrap();
+        with_bundle(
+            self,
+            endpoint,
+            crate::style::Bundle::File(main_css_path.as_ref().to_path_buf()),
+        )
+    }
+
+    fn with_css_modules<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self {
+        with_bundle(
+            self,
+            endpoint,
+            crate::style::Bundle::Modules(dir.as_ref().to_path_buf()),
+        )
+    }
 
-        crate::hmr::watch_style();
+    #[cfg(all(debug_assertions, feature = "bundle-js"))]
+    fn with_bundled_js<P: AsRef<Path>>(self, endpoint: &str, entry: P) -> Self {
+        crate::script::add_script(endpoint, entry.as_ref()).unwrap_or_else(|e| panic!("{e}"));
+        crate::hmr::watch_script(endpoint, entry.as_ref());
 
-        self.route(endpoint, axum::routing::get(main_css))
+        let owned_endpoint = endpoint.to_string();
+        self.route(
+            endpoint,
+            axum::routing::get(move |headers: axum::http::HeaderMap| {
+                let script = crate::script::script_at(&owned_endpoint);
+                async move { bundled(&headers, "text/javascript", script) }
+            }),
+        )
+    }
+
+    #[cfg(all(not(debug_assertions), feature = "bundle-js"))]
+    fn with_bundled_js<P: AsRef<Path>>(self, endpoint: &str, entry: P) -> Self {
+        crate::script::add_script(endpoint, entry.as_ref()).unwrap_or_else(|e| panic!("{e}"));
+
+        match crate::script::release_script(entry.as_ref()) {
+            Ok(js) => serve_bundle(self, endpoint, "text/javascript", js),
+            Err(message) => self.route(
+                endpoint,
+                axum::routing::get(move || {
+                    std::future::ready((
+                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        message.clone(),
+                    ))
+                }),
+            ),
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn with_assets<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self {
+        crate::hmr::watch_assets(endpoint, dir.as_ref());
+
+        let dir = dir.as_ref().to_path_buf();
+        self.route(
+            &format!("{}/*path", endpoint.trim_end_matches('/')),
+            axum::routing::get(
+                move |headers: axum::http::HeaderMap,
+                      axum::extract::Path(path): axum::extract::Path<String>| {
+                    let asset = crate::asset::Asset::read(&dir, &path);
+                    async move {
+                        match asset {
+                            Some(asset) => {
+                                asset_response(&headers, &asset, crate::asset::CACHE_REVALIDATE)
+                            }
+                            None => axum::http::StatusCode::NOT_FOUND.into_response(),
+                        }
+                    }
+                },
+            ),
+        )
     }
 
     #[cfg(not(debug_assertions))]
-    fn with_bundled_css<P: AsRef<Path>>(self, endpoint: &str, main_css_path: P) -> Self {
-        crate::style::MAIN_CSS
-            .set(crate::style::transform_css(&main_css_path.as_ref().to_path_buf()).unwrap())
-            .unwrap();
+    fn with_assets<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self {
+        use crate::asset::{CACHE_IMMUTABLE, CACHE_REVALIDATE};
+
+        crate::asset::assets_in(endpoint, dir.as_ref())
+            .into_iter()
+            .fold(self, |router, (url, asset)| {
+                // Assets are read once, and shared by both of their routes.
+                let asset = std::sync::Arc::new(asset);
+                let fingerprinted = crate::asset::fingerprint(&url, asset.hash());
+                let immutable = asset.clone();
+
+                router
+                    .route(
+                        &url,
+                        axum::routing::get(move |headers: axum::http::HeaderMap| {
+                            std::future::ready(asset_response(&headers, &asset, CACHE_REVALIDATE))
+                        }),
+                    )
+                    .route(
+                        &fingerprinted,
+                        axum::routing::get(move |headers: axum::http::HeaderMap| {
+                            std::future::ready(asset_response(
+                                &headers,
+                                &immutable,
+                                CACHE_IMMUTABLE,
+                            ))
+                        }),
+                    )
+            })
+    }
+
+    fn with_event_stream(self, endpoint: &str) -> Self {
+        use crate::runtime::StreamExt;
+        use axum::response::sse::{Event, KeepAlive, Sse};
+
+        let owned_endpoint = endpoint.to_string();
+        self.route(
+            endpoint,
+            axum::routing::get(move || {
+                let events =
+                    crate::runtime::broadcast_stream(crate::sse::subscribe(&owned_endpoint));
+                async move {
+                    Sse::new(events.map(|event| {
+                        Ok::<_, std::convert::Infallible>(
+                            Event::default().event(&event.name).data(&event.data),
+                        )
+                    }))
+                    .keep_alive(KeepAlive::default())
+                }
+            }),
+        )
+    }
+
+    fn with_websocket<H, Fut>(self, endpoint: &str, handler: H) -> Self
+    where
+        H: Fn(crate::SocketMessage) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<String, crate::Error>> + Send + 'static,
+    {
+        let handler = crate::socket::handler(handler);
+        let owned_endpoint = endpoint.to_string();
+        self.route(
+            endpoint,
+            axum::routing::get(move |ws: axum::extract::WebSocketUpgrade| {
+                let (handler, endpoint) = (handler.clone(), owned_endpoint.clone());
+                async move {
+                    ws.on_upgrade(move |socket| crate::socket::serve(socket, endpoint, handler))
+                }
+            }),
+        )
+    }
+
+    fn with_template_routes(self) -> Self {
+        let routes = crate::template_names()
+            .iter()
+            .filter_map(|name| crate::route_of_template(name))
+            .fold(Router::new(), |router, route| {
+                router.route(&route, axum::routing::get(template_route))
+            });
 
-        self.route(endpoint, axum::routing::get(main_css))
+        // Merging fails if both routers have a fallback, rather than replacing the router's own.
+        self.merge(Router::new().fallback_service(routes))
     }
 }
 
+async fn template_route(template: crate::Template) -> Result<RenderedTemplate, crate::Error> {
+    template.try_render(crate::context!())
+}
+
 #[cfg(debug_assertions)]
-async fn main_css() -> impl IntoResponse {
-    (
-        [("Content-Type", "text/css")],
-        crate::style::transform_css(crate::style::STYLE_MAIN_FILE.get().unwrap()).unwrap(),
+fn with_bundle(router: Router, endpoint: &str, bundle: crate::style::Bundle) -> Router {
+    crate::hmr::watch_style(bundle.path());
+    // Bundled right away, so that CSS modules are available before their stylesheet is requested.
+    crate::style::transform_bundle(&bundle).ok();
+    crate::style::add_bundle(endpoint, bundle).unwrap_or_else(|e| panic!("{e}"));
+
+    let owned_endpoint = endpoint.to_string();
+    router.route(
+        endpoint,
+        axum::routing::get(move |headers: axum::http::HeaderMap| {
+            let css = crate::style::bundle_at(&owned_endpoint);
+            async move { bundled(&headers, "text/css", css) }
+        }),
     )
 }
 
 #[cfg(not(debug_assertions))]
-async fn main_css() -> impl IntoResponse {
-    (
-        [("Content-Type", "text/css")],
-        crate::style::MAIN_CSS.get().unwrap().as_str(),
-    )
+fn with_bundle(router: Router, endpoint: &str, bundle: crate::style::Bundle) -> Router {
+    let css = bundle
+        .transform()
+        .unwrap_or_else(|e| panic!("Error while bundling CSS: {e}"));
+    crate::style::add_bundle(endpoint, bundle).unwrap_or_else(|e| panic!("{e}"));
+
+    serve_bundle(router, endpoint, "text/css", css)
+}
+
+/// Serves a bundle at `endpoint`, and at its fingerprinted URL.
+#[cfg(not(debug_assertions))]
+fn serve_bundle(
+    router: Router,
+    endpoint: &str,
+    content_type: &'static str,
+    body: String,
+) -> Router {
+    use crate::asset::{CACHE_IMMUTABLE, CACHE_REVALIDATE};
+
+    // Bundles are built once, and served for as long as the program runs.
+    let body: &'static str = Box::leak(body.into_boxed_str());
+    let hash = crate::asset::hash_of(body.as_bytes());
+    let etag: &'static str = Box::leak(crate::asset::etag_of(&hash).into_boxed_str());
+    let fingerprinted = crate::asset::fingerprint(endpoint, &hash);
+
+    router
+        .route(
+            endpoint,
+            axum::routing::get(move |headers: axum::http::HeaderMap| async move {
+                cached(&headers, content_type, etag, CACHE_REVALIDATE, body)
+            }),
+        )
+        .route(
+            &fingerprinted,
+            axum::routing::get(move |headers: axum::http::HeaderMap| async move {
+                cached(&headers, content_type, etag, CACHE_IMMUTABLE, body)
+            }),
+        )
+}
+
+/// Responds with a bundle built for this request, or with its error.
+#[cfg(debug_assertions)]
+fn bundled(
+    headers: &axum::http::HeaderMap,
+    content_type: &str,
+    bundle: Option<Result<String, crate::overlay::CompileError>>,
+) -> axum::response::Response {
+    match bundle {
+        Some(Ok(body)) => {
+            let etag = crate::asset::etag_of(&crate::asset::hash_of(body.as_bytes()));
+            cached(
+                headers,
+                content_type,
+                &etag,
+                crate::asset::CACHE_REVALIDATE,
+                body,
+            )
+        }
+        Some(Err(e)) => {
+            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+        None => axum::http::StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Responds with `body`, or with a 304 if the client's copy is still current.
+fn cached<B: IntoResponse>(
+    headers: &axum::http::HeaderMap,
+    content_type: &str,
+    etag: &str,
+    cache_control: &str,
+    body: B,
+) -> axum::response::Response {
+    let if_none_match = headers
+        .get(axum::http::header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+    let cache_headers = [("ETag", etag), ("Cache-Control", cache_control)];
+
+    if crate::asset::is_fresh(if_none_match, etag) {
+        (axum::http::StatusCode::NOT_MODIFIED, cache_headers).into_response()
+    } else {
+        (cache_headers, [("Content-Type", content_type)], body).into_response()
+    }
+}
+
+/// Responds with an asset, compressed if the client accepts it.
+fn asset_response(
+    headers: &axum::http::HeaderMap,
+    asset: &crate::asset::Asset,
+    cache_control: &str,
+) -> axum::response::Response {
+    use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, VARY};
+    use axum::http::HeaderValue;
+
+    let accept_encoding = headers
+        .get(ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok());
+    let (body, encoding) = asset.encoded(accept_encoding);
+
+    let mut response = cached(
+        headers,
+        &asset.content_type,
+        &asset.etag,
+        cache_control,
+        axum::body::Bytes::copy_from_slice(body),
+    );
+    if let Some(encoding) = encoding.filter(|_| response.status().is_success()) {
+        response
+            .headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+    response
+        .headers_mut()
+        .insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+    response
+}
+
+impl IntoResponse for crate::Error {
+    fn into_response(self) -> axum::response::Response {
+        let status = axum::http::StatusCode::from_u16(self.status())
+            .unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        (status, self.body()).into_response()
+    }
 }
 
 #[async_trait]
-impl<S, B> FromRequest<S, B> for crate::template::Template
+impl<S, B, F> FromRequest<S, B> for crate::template::Template<F>
 where
-    axum::Form<HashMap<String, String>>: FromRequest<S, B>,
+    axum::Form<HashMap<String, String>>:
+        FromRequest<S, B, Rejection = axum::extract::rejection::FormRejection>,
     S: Send + Sync + std::fmt::Debug,
     B: Send + 'static + std::fmt::Debug,
+    F: serde::de::DeserializeOwned,
 {
-    type Rejection = ();
+    type Rejection = crate::Error;
 
-    #[cfg(not(debug_assertions))]
     async fn from_request(req: axum::http::Request<B>, state: &S) -> Result<Self, Self::Rejection> {
-        use axum::RequestPartsExt;
-        let (mut parts, body) = req.into_parts();
+        extract_template(req, state).await?.deserialize()
+    }
+}
+
+#[async_trait]
+impl<S, B, F> FromRequest<S, B> for crate::Validated<F>
+where
+    axum::Form<HashMap<String, String>>:
+        FromRequest<S, B, Rejection = axum::extract::rejection::FormRejection>,
+    S: Send + Sync + std::fmt::Debug,
+    B: Send + 'static + std::fmt::Debug,
+    F: serde::de::DeserializeOwned + crate::Validate,
+{
+    type Rejection = axum::response::Response;
 
-        let endpoint = parts
-            .extract::<axum::extract::MatchedPath>()
+    async fn from_request(req: axum::http::Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        extract_template(req, state)
             .await
-            .map(|path| path.as_str().to_owned())
-            .unwrap();
+            .map_err(IntoResponse::into_response)?
+            .validate()
+            .map(crate::Validated)
+            .map_err(IntoResponse::into_response)
+    }
+}
 
-        let req = axum::http::Request::from_parts(parts, body);
+#[cfg(not(debug_assertions))]
+async fn extract_template<S, B>(
+    req: axum::http::Request<B>,
+    state: &S,
+) -> Result<crate::template::Template, crate::Error>
+where
+    axum::Form<HashMap<String, String>>:
+        FromRequest<S, B, Rejection = axum::extract::rejection::FormRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    use axum::RequestPartsExt;
+    let (mut parts, body) = req.into_parts();
 
-        match axum::Form::<HashMap<String, String>>::from_request(req, state).await {
-            Ok(axum::Form(form)) => Ok(Self {
-                path: endpoint,
-                form,
-            }),
-            Err(_) => Err(()),
-        }
+    // Handlers that aren't behind a route, like fallbacks, have no endpoint to find a template for.
+    let endpoint = parts
+        .extract::<axum::extract::MatchedPath>()
+        .await
+        .map(|path| path.as_str().to_owned())
+        .map_err(|_| crate::Error::MissingTemplate(parts.uri.path().to_owned()))?;
+
+    let htmx = htmx_of(&parts.headers);
+    let params = params_of(&mut parts).await;
+    let req = axum::http::Request::from_parts(parts, body);
+
+    match axum::Form::<HashMap<String, String>>::from_request(req, state).await {
+        Ok(axum::Form(form)) => Ok(crate::template::Template {
+            path: endpoint,
+            form,
+            htmx,
+            params,
+        }),
+        Err(e) => Err(form_rejection(e)),
     }
+}
 
-    #[cfg(debug_assertions)]
-    async fn from_request(req: axum::http::Request<B>, state: &S) -> Result<Self, Self::Rejection> {
-        use axum::RequestPartsExt;
+#[cfg(debug_assertions)]
+async fn extract_template<S, B>(
+    req: axum::http::Request<B>,
+    state: &S,
+) -> Result<crate::template::Template, crate::Error>
+where
+    axum::Form<HashMap<String, String>>:
+        FromRequest<S, B, Rejection = axum::extract::rejection::FormRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    use axum::RequestPartsExt;
 
-        let (mut parts, body) = req.into_parts();
+    let (mut parts, body) = req.into_parts();
 
-        let this_endpoint = parts
-            .extract::<axum::extract::MatchedPath>()
-            .await
-            .map(|path| path.as_str().to_owned())
-            .unwrap();
+    // Handlers that aren't behind a route, like fallbacks, have no endpoint to find a template for.
+    let this_endpoint = parts
+        .extract::<axum::extract::MatchedPath>()
+        .await
+        .map(|path| path.as_str().to_owned())
+        .map_err(|_| crate::Error::MissingTemplate(parts.uri.path().to_owned()))?;
 
-        let ip = parts
-            .extract::<axum::extract::ConnectInfo<SocketAddr>>()
-            .await
-            .unwrap()
-            .ip();
+    let client = crate::template::hmr_client(
+        parts
+            .headers
+            .get(crate::template::HMR_CLIENT_HEADER)
+            .and_then(|value| value.to_str().ok()),
+        parts
+            .headers
+            .get_all(axum::http::header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok()),
+    )
+    .map(str::to_owned);
 
-        let req = axum::http::Request::from_parts(parts, body);
+    let htmx = htmx_of(&parts.headers);
+    let params = params_of(&mut parts).await;
+    let req = axum::http::Request::from_parts(parts, body);
 
-        axum::Form::from_request(req, state)
-            .await
-            .map(|form| crate::template::template_hydrate(ip, this_endpoint, form.0))
-            .map_err(|_| ())
+    match axum::Form::<HashMap<String, String>>::from_request(req, state).await {
+        Ok(axum::Form(form)) => Ok(crate::template::template_hydrate(
+            client.as_deref(),
+            this_endpoint,
+            form,
+            htmx,
+            params,
+        )),
+        Err(e) => Err(form_rejection(e)),
+    }
+}
+
+/// The matched route's parameters. Routes without any have none to extract.
+async fn params_of(parts: &mut axum::http::request::Parts) -> HashMap<String, String> {
+    use axum::RequestPartsExt;
+
+    parts
+        .extract::<axum::extract::Path<HashMap<String, String>>>()
+        .await
+        .map(|params| params.0)
+        .unwrap_or_default()
+}
+
+fn htmx_of(headers: &axum::http::HeaderMap) -> crate::Htmx {
+    crate::Htmx::from_headers(|name| headers.get(name).and_then(|v| v.to_str().ok()))
+}
+
+#[async_trait]
+impl<S: Send + Sync> axum::extract::FromRequestParts<S> for crate::Htmx {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(htmx_of(&parts.headers))
+    }
+}
+
+impl axum::response::IntoResponseParts for crate::HtmxResponse {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(
+        self,
+        mut res: axum::response::ResponseParts,
+    ) -> Result<axum::response::ResponseParts, Self::Error> {
+        for (name, value) in self.headers() {
+            match value.parse() {
+                Ok(value) => {
+                    res.headers_mut().insert(*name, value);
+                }
+                Err(_) => error!("Invalid value for {name}: {value}"),
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl IntoResponse for crate::HtmxResponse {
+    fn into_response(self) -> axum::response::Response {
+        (self, ()).into_response()
+    }
+}
+
+fn form_rejection(e: axum::extract::rejection::FormRejection) -> crate::Error {
+    crate::Error::InvalidForm {
+        field: None,
+        message: e.body_text(),
+    }
+}
+
+impl IntoResponse for crate::Invalid {
+    fn into_response(self) -> axum::response::Response {
+        let status = axum::http::StatusCode::from_u16(self.status())
+            .unwrap_or(axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+
+        match self.render() {
+            Ok(rendered) => (status, self.response(), rendered).into_response(),
+            Err(e) => e.into_response(),
+        }
     }
 }
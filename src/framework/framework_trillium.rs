@@ -1,4 +1,5 @@
 use crate::template::Template;
+use serde::de::DeserializeOwned;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::path::Path;
@@ -11,13 +12,77 @@ pub fn into_rendered_template(cow: Cow<'static, str>) -> RenderedTemplate {
     cow
 }
 
+pub fn from_rendered_template(rendered: RenderedTemplate) -> Cow<'static, str> {
+    rendered
+}
+
+/// A template that's rendered while it's being sent. See [Template::render_stream](crate::Template::render_stream).
+/// Converts into a [trillium::Body], so it can be passed to `Conn::with_body`.
+pub struct StreamedTemplate(crate::runtime::MpscReceiver<String>);
+
+pub fn into_streamed_template(chunks: crate::runtime::MpscReceiver<String>) -> StreamedTemplate {
+    StreamedTemplate(chunks)
+}
+
+impl From<StreamedTemplate> for trillium::Body {
+    fn from(template: StreamedTemplate) -> Self {
+        trillium::Body::new_streaming(
+            ChunkReader {
+                chunks: Box::pin(template.0),
+                chunk: Vec::new(),
+                read: 0,
+            },
+            None,
+        )
+    }
+}
+
+/// Reads the chunks of a [StreamedTemplate] as they're rendered, or the events of an event stream
+/// as they're published.
+struct ChunkReader {
+    chunks: std::pin::Pin<Box<dyn crate::runtime::Stream<Item = String> + Send + Sync>>,
+    chunk: Vec<u8>,
+    read: usize,
+}
+
+impl crate::runtime::AsyncRead for ChunkReader {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        use std::task::Poll;
+
+        while self.read == self.chunk.len() {
+            match self.chunks.as_mut().poll_next(cx) {
+                Poll::Ready(Some(chunk)) => {
+                    self.chunk = chunk.into_bytes();
+                    self.read = 0;
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let len = buf.len().min(self.chunk.len() - self.read);
+        buf[..len].copy_from_slice(&self.chunk[self.read..self.read + len]);
+        self.read += len;
+        Poll::Ready(Ok(len))
+    }
+}
+
 #[cfg(debug_assertions)]
 pub async fn hmr_websocket(conn: WebSocket) {
-    let ip = conn.peer_ip().unwrap();
-    crate::hmr::hmr_handler(conn, ip).await;
+    let client = crate::hmr::client_of_query(conn.querystring());
+    crate::hmr::hmr_handler(conn, client).await;
 }
 
 #[cfg(debug_assertions)]
+pub async fn hmr_state(conn: trillium::Conn) -> trillium::Conn {
+    conn.with_header("Content-Type", "application/json")
+        .ok(crate::hmr::state_json())
+}
+
 pub async fn websocket_recv(
     socket: &mut WebSocket,
 ) -> Option<Result<trillium_websockets::tungstenite::Message, trillium_websockets::Error>> {
@@ -26,12 +91,6 @@ pub async fn websocket_recv(
     socket.next().await
 }
 
-#[cfg(debug_assertions)]
-pub async fn websocket_unwrap<T>(socket: async_channel::Send<'_, T>) {
-    socket.await.unwrap();
-}
-
-#[cfg(debug_assertions)]
 pub fn websocket_message_text(text: String) -> trillium_websockets::tungstenite::Message {
     trillium_websockets::tungstenite::Message::Text(text)
 }
@@ -41,7 +100,6 @@ pub fn websocket_message_binary(data: Vec<u8>) -> trillium_websockets::tungsteni
     trillium_websockets::tungstenite::Message::Binary(data)
 }
 
-#[cfg(debug_assertions)]
 pub fn websocket_try_as_text(message: trillium_websockets::tungstenite::Message) -> Option<String> {
     match message {
         trillium_websockets::tungstenite::Message::Text(t) => Some(t),
@@ -61,7 +119,34 @@ pub fn websocket_try_as_binary(
 
 pub trait RouterExt {
     fn with_hmr(self) -> Self;
+    /// Bundles a stylesheet with everything it `@import`s, serving it at `endpoint`.
+    /// Can be called once for every stylesheet, like `/admin.css` and `/public.css`.
     fn with_bundled_css<P: AsRef<Path>>(self, endpoint: &str, main_css_path: P) -> Self;
+    /// Serves every stylesheet in `dir` as a CSS module at `endpoint`. Templates get the scoped
+    /// class names of a module with `css_module`, e.g. `css_module("card.css").title`.
+    fn with_css_modules<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self;
+    /// Bundles a JavaScript or TypeScript module with everything it imports, serving it at
+    /// `endpoint`. Only relative imports are bundled. Bundling errors are logged, and served
+    /// with a 500 in place of the script. Needs the `bundle-js` feature.
+    #[cfg(feature = "bundle-js")]
+    fn with_bundled_js<P: AsRef<Path>>(self, endpoint: &str, entry: P) -> Self;
+    /// Serves every file in `dir` under `endpoint`, like `/assets/logo.png`. Release builds
+    /// precompress each file, and also serve it at a fingerprinted URL, resolved with `asset_url`.
+    fn with_assets<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self;
+    /// Serves a stream of server-sent events at `endpoint`, which sends every template published
+    /// to it with [publish](crate::publish) to each connected client.
+    fn with_event_stream(self, endpoint: &str) -> Self;
+    /// Serves a websocket at `endpoint` for htmx's `ws` extension. Each form sent with `ws-send` is
+    /// passed to `handler`, and whatever it renders is sent back, usually with [render_oob](crate::render_oob).
+    /// Fragments can also be pushed to a connection at any time, through [Socket](crate::Socket) or
+    /// [broadcast](crate::broadcast).
+    fn with_websocket<H, Fut>(self, endpoint: &str, handler: H) -> Self
+    where
+        H: Fn(crate::SocketMessage) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<String, crate::Error>> + Send + 'static;
+    /// Serves every template in the template directory at its own route, for requests that no
+    /// other route matches. `users/[id].html.jinja2` is served at `/users/:id`.
+    fn with_template_routes(self) -> Self;
 }
 
 impl RouterExt for trillium_router::Router {
@@ -69,73 +154,446 @@ impl RouterExt for trillium_router::Router {
     fn with_hmr(self) -> Self {
         crate::hmr::watch_templates();
         self.get("/hmr", trillium_websockets::websocket(hmr_websocket))
+            .get("/hmr/state", hmr_state)
     }
 
     #[cfg(not(debug_assertions))]
     fn with_hmr(self) -> Self {
+        once_cell::sync::Lazy::force(&crate::render::ENVIRONMENT);
         self
     }
 
-    #[cfg(debug_assertions)]
     fn with_bundled_css<P: AsRef<Path>>(self, endpoint: &str, main_css_path: P) -> Self {
-        crate::style::STYLE_MAIN_FILE
-            .set(main_css_path.as_ref().to_path_buf())
-            .unwrap();
+        with_bundle(
+            self,
+            endpoint,
+            crate::style::Bundle::File(main_css_path.as_ref().to_path_buf()),
+        )
+    }
+
+    fn with_css_modules<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self {
+        with_bundle(
+            self,
+            endpoint,
+            crate::style::Bundle::Modules(dir.as_ref().to_path_buf()),
+        )
+    }
+
+    #[cfg(all(debug_assertions, feature = "bundle-js"))]
+    fn with_bundled_js<P: AsRef<Path>>(self, endpoint: &str, entry: P) -> Self {
+        crate::script::add_script(endpoint, entry.as_ref()).unwrap_or_else(|e| panic!("{e}"));
+        crate::hmr::watch_script(endpoint, entry.as_ref());
 
-        crate::hmr::watch_style();
+        let owned_endpoint = endpoint.to_string();
+        self.get(endpoint, move |conn: trillium::Conn| {
+            let script = crate::script::script_at(&owned_endpoint);
+            async move { bundled(conn, "text/javascript", script) }
+        })
+    }
+
+    #[cfg(all(not(debug_assertions), feature = "bundle-js"))]
+    fn with_bundled_js<P: AsRef<Path>>(self, endpoint: &str, entry: P) -> Self {
+        crate::script::add_script(endpoint, entry.as_ref()).unwrap_or_else(|e| panic!("{e}"));
+
+        match crate::script::release_script(entry.as_ref()) {
+            Ok(js) => serve_bundle(self, endpoint, "text/javascript", js),
+            Err(message) => self.get(endpoint, move |conn: trillium::Conn| {
+                std::future::ready(conn.with_status(500).with_body(message.clone()).halt())
+            }),
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn with_assets<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self {
+        use trillium_router::RouterConnExt;
+
+        crate::hmr::watch_assets(endpoint, dir.as_ref());
 
-        self.get(endpoint, main_css)
+        let dir = dir.as_ref().to_path_buf();
+        self.get(
+            format!("{}/*", endpoint.trim_end_matches('/')),
+            move |conn: trillium::Conn| {
+                let asset = crate::asset::Asset::read(&dir, conn.wildcard().unwrap_or_default());
+                async move {
+                    match asset {
+                        Some(asset) => asset_response(conn, &asset, crate::asset::CACHE_REVALIDATE),
+                        None => conn.with_status(404),
+                    }
+                }
+            },
+        )
     }
 
     #[cfg(not(debug_assertions))]
-    fn with_bundled_css<P: AsRef<Path>>(self, endpoint: &str, main_css_path: P) -> Self {
-        crate::style::MAIN_CSS
-            .set(crate::style::transform_css(&main_css_path.as_ref().to_path_buf()).unwrap())
-            .unwrap();
+    fn with_assets<P: AsRef<Path>>(self, endpoint: &str, dir: P) -> Self {
+        use crate::asset::{CACHE_IMMUTABLE, CACHE_REVALIDATE};
+
+        crate::asset::assets_in(endpoint, dir.as_ref())
+            .into_iter()
+            .fold(self, |router, (url, asset)| {
+                // Assets are read once, and shared by both of their routes.
+                let asset = std::sync::Arc::new(asset);
+                let fingerprinted = crate::asset::fingerprint(&url, asset.hash());
+                let immutable = asset.clone();
 
-        self.get(endpoint, main_css)
+                router
+                    .get(url, move |conn: trillium::Conn| {
+                        std::future::ready(asset_response(conn, &asset, CACHE_REVALIDATE))
+                    })
+                    .get(fingerprinted, move |conn: trillium::Conn| {
+                        std::future::ready(asset_response(conn, &immutable, CACHE_IMMUTABLE))
+                    })
+            })
+    }
+
+    fn with_event_stream(self, endpoint: &str) -> Self {
+        use crate::runtime::StreamExt;
+
+        let owned_endpoint = endpoint.to_string();
+        self.get(endpoint, move |conn: trillium::Conn| {
+            let events = crate::runtime::broadcast_stream(crate::sse::subscribe(&owned_endpoint));
+            // Sends a comment whenever the stream is idle, so that proxies don't close it.
+            let chunks = crate::runtime::stream_unfold(Box::pin(events), |mut events| async move {
+                let chunk = crate::runtime::race(
+                    async { events.next().await.map(|event| encode_event(&event)) },
+                    async {
+                        crate::runtime::sleep(SSE_KEEP_ALIVE).await;
+                        Some(":\n\n".to_string())
+                    },
+                )
+                .await?;
+                Some((chunk, events))
+            });
+            async move {
+                let body = ChunkReader {
+                    chunks: Box::pin(chunks),
+                    chunk: Vec::new(),
+                    read: 0,
+                };
+
+                conn.with_header("Content-Type", "text/event-stream")
+                    .with_header("Cache-Control", "no-cache")
+                    .ok(trillium::Body::new_streaming(body, None))
+            }
+        })
+    }
+
+    fn with_websocket<H, Fut>(self, endpoint: &str, handler: H) -> Self
+    where
+        H: Fn(crate::SocketMessage) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<String, crate::Error>> + Send + 'static,
+    {
+        let handler = crate::socket::handler(handler);
+        let owned_endpoint = endpoint.to_string();
+        self.get(
+            endpoint,
+            trillium_websockets::websocket(move |socket: WebSocket| {
+                crate::socket::serve(socket, owned_endpoint.clone(), handler.clone())
+            }),
+        )
+    }
+
+    fn with_template_routes(self) -> Self {
+        let routes = crate::template_names()
+            .iter()
+            .filter_map(|name| crate::route_of_template(name))
+            .fold(trillium_router::Router::new(), |router, route| {
+                router.get(route.clone(), move |conn| {
+                    template_route(conn, route.clone())
+                })
+            });
+
+        self.get("*", routes)
+    }
+}
+
+/// The route is passed along as it was registered, since the router doesn't keep trailing slashes.
+async fn template_route(mut conn: trillium::Conn, route: String) -> trillium::Conn {
+    use trillium::Handler;
+
+    let mut template = conn.template().await;
+    template.path = route;
+
+    match template.try_render(crate::context!()) {
+        Ok(rendered) => conn
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .ok(rendered.into_owned()),
+        Err(e) => e.run(conn).await,
     }
 }
 
 #[cfg(debug_assertions)]
-async fn main_css(conn: trillium::Conn) -> trillium::Conn {
-    conn.with_header("Content-Type", "text/css").with_body(
-        crate::style::transform_css(crate::style::STYLE_MAIN_FILE.get().unwrap()).unwrap(),
-    )
+fn with_bundle(
+    router: trillium_router::Router,
+    endpoint: &str,
+    bundle: crate::style::Bundle,
+) -> trillium_router::Router {
+    crate::hmr::watch_style(bundle.path());
+    // Bundled right away, so that CSS modules are available before their stylesheet is requested.
+    crate::style::transform_bundle(&bundle).ok();
+    crate::style::add_bundle(endpoint, bundle).unwrap_or_else(|e| panic!("{e}"));
+
+    let owned_endpoint = endpoint.to_string();
+    router.get(endpoint, move |conn: trillium::Conn| {
+        let css = crate::style::bundle_at(&owned_endpoint);
+        async move { bundled(conn, "text/css", css) }
+    })
 }
 
 #[cfg(not(debug_assertions))]
-async fn main_css(conn: trillium::Conn) -> trillium::Conn {
-    conn.with_header("Content-Type", "text/css")
-        .with_body(crate::style::MAIN_CSS.get().unwrap().as_str())
+fn with_bundle(
+    router: trillium_router::Router,
+    endpoint: &str,
+    bundle: crate::style::Bundle,
+) -> trillium_router::Router {
+    let css = bundle
+        .transform()
+        .unwrap_or_else(|e| panic!("Error while bundling CSS: {e}"));
+    crate::style::add_bundle(endpoint, bundle).unwrap_or_else(|e| panic!("{e}"));
+
+    serve_bundle(router, endpoint, "text/css", css)
+}
+
+/// Serves a bundle at `endpoint`, and at its fingerprinted URL.
+#[cfg(not(debug_assertions))]
+fn serve_bundle(
+    router: trillium_router::Router,
+    endpoint: &str,
+    content_type: &'static str,
+    body: String,
+) -> trillium_router::Router {
+    use crate::asset::{CACHE_IMMUTABLE, CACHE_REVALIDATE};
+
+    // Bundles are built once, and served for as long as the program runs.
+    let body: &'static str = Box::leak(body.into_boxed_str());
+    let hash = crate::asset::hash_of(body.as_bytes());
+    let etag: &'static str = Box::leak(crate::asset::etag_of(&hash).into_boxed_str());
+    let fingerprinted = crate::asset::fingerprint(endpoint, &hash);
+
+    router
+        .get(endpoint, move |conn: trillium::Conn| async move {
+            cached(conn, content_type, etag, CACHE_REVALIDATE, body)
+        })
+        .get(fingerprinted, move |conn: trillium::Conn| async move {
+            cached(conn, content_type, etag, CACHE_IMMUTABLE, body)
+        })
+}
+
+/// Responds with a bundle built for this request, or with its error.
+#[cfg(debug_assertions)]
+fn bundled(
+    conn: trillium::Conn,
+    content_type: &str,
+    bundle: Option<Result<String, crate::overlay::CompileError>>,
+) -> trillium::Conn {
+    match bundle {
+        Some(Ok(body)) => {
+            let etag = crate::asset::etag_of(&crate::asset::hash_of(body.as_bytes()));
+            cached(
+                conn,
+                content_type,
+                &etag,
+                crate::asset::CACHE_REVALIDATE,
+                body,
+            )
+        }
+        Some(Err(e)) => conn.with_status(500).with_body(e.to_string()).halt(),
+        None => conn.with_status(404).halt(),
+    }
+}
+
+/// How long an event stream can stay idle before a keep-alive comment is sent, matching Axum.
+const SSE_KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Frames an event in the `text/event-stream` format.
+fn encode_event(event: &crate::sse::Event) -> String {
+    let mut encoded = format!("event: {}\n", event.name);
+    for line in event.data.split('\n') {
+        encoded += &format!("data: {line}\n");
+    }
+    encoded + "\n"
+}
+
+/// Responds with `body`, or with a 304 if the client's copy is still current.
+fn cached<B: Into<trillium::Body>>(
+    conn: trillium::Conn,
+    content_type: &str,
+    etag: &str,
+    cache_control: &'static str,
+    body: B,
+) -> trillium::Conn {
+    let fresh = crate::asset::is_fresh(conn.request_headers().get_str("If-None-Match"), etag);
+    let conn = conn
+        .with_header("ETag", etag.to_string())
+        .with_header("Cache-Control", cache_control);
+
+    if fresh {
+        conn.with_status(304).halt()
+    } else {
+        conn.with_header("Content-Type", content_type.to_string())
+            .ok(body)
+    }
+}
+
+/// Responds with an asset, compressed if the client accepts it.
+fn asset_response(
+    conn: trillium::Conn,
+    asset: &crate::asset::Asset,
+    cache_control: &'static str,
+) -> trillium::Conn {
+    let (body, encoding) = asset.encoded(conn.request_headers().get_str("Accept-Encoding"));
+    let fresh =
+        crate::asset::is_fresh(conn.request_headers().get_str("If-None-Match"), &asset.etag);
+
+    let conn = cached(
+        conn,
+        &asset.content_type,
+        &asset.etag,
+        cache_control,
+        body.to_vec(),
+    )
+    .with_header("Vary", "Accept-Encoding");
+
+    match encoding {
+        Some(encoding) if !fresh => conn.with_header("Content-Encoding", encoding),
+        _ => conn,
+    }
+}
+
+#[trillium::async_trait]
+impl trillium::Handler for crate::Error {
+    async fn run(&self, conn: trillium::Conn) -> trillium::Conn {
+        conn.with_status(self.status())
+            .with_body(self.body())
+            .halt()
+    }
+}
+
+#[trillium::async_trait]
+impl trillium::Handler for crate::HtmxResponse {
+    async fn run(&self, conn: trillium::Conn) -> trillium::Conn {
+        self.headers().iter().fold(conn, |conn, (name, value)| {
+            conn.with_header(*name, value.clone())
+        })
+    }
+}
+
+#[trillium::async_trait]
+impl trillium::Handler for crate::Invalid {
+    async fn run(&self, conn: trillium::Conn) -> trillium::Conn {
+        let conn = self.response().run(conn).await;
+
+        match self.render() {
+            Ok(rendered) => conn
+                .with_status(self.status())
+                .with_body(rendered.into_owned())
+                .halt(),
+            Err(e) => e.run(conn).await,
+        }
+    }
+}
+
+/// The endpoint of the conn's template, and the parameters of its route. Routes with parameters
+/// use their pattern, like `/users/:id`, and other routes use the path they were requested at.
+fn route_of(conn: &trillium::Conn) -> (String, HashMap<String, String>) {
+    use trillium_router::RouterConnExt;
+
+    let route = conn.route().map(ToString::to_string).unwrap_or_default();
+    let params: HashMap<String, String> = route
+        .split('/')
+        .filter_map(|segment| segment.strip_prefix(':'))
+        .filter_map(|param| Some((param.to_string(), conn.param(param)?.to_string())))
+        .collect();
+
+    if params.is_empty() {
+        (conn.path().to_owned(), params)
+    } else {
+        (route, params)
+    }
 }
 
 #[trillium::async_trait]
 pub trait ConnExt {
     async fn template(&mut self) -> Template;
+
+    fn htmx(&self) -> crate::Htmx;
+
+    /// Like [ConnExt::template], but deserializes the query into `F`.
+    /// Run the error as a handler to respond with a 422.
+    async fn typed_template<F: DeserializeOwned>(&mut self) -> Result<Template<F>, crate::Error>;
+
+    /// Like [ConnExt::typed_template], but also validates the form. Run the error as a handler
+    /// to render the template again with the form's `errors`.
+    async fn validated_template<F: DeserializeOwned + crate::Validate>(
+        &mut self,
+    ) -> Result<Template<F>, crate::Invalid>;
 }
 
 #[trillium::async_trait]
 impl ConnExt for trillium::Conn {
     #[cfg(not(debug_assertions))]
     async fn template(&mut self) -> Template {
-        let path = self.path().to_owned();
+        let (path, params) = route_of(self);
         let form: HashMap<String, String> =
             serde_urlencoded::from_str::<HashMap<String, String>>(self.querystring())
                 .unwrap_or_default();
 
-        Template { path, form }
+        Template {
+            path,
+            form,
+            htmx: self.htmx(),
+            params,
+        }
     }
 
     #[cfg(debug_assertions)]
     async fn template(&mut self) -> Template {
-        let path = self.path().to_owned();
-        let ip = self.peer_ip().unwrap();
+        let (path, params) = route_of(self);
+        let client = crate::template::hmr_client(
+            self.headers().get_str(crate::template::HMR_CLIENT_HEADER),
+            self.headers()
+                .get_values("Cookie")
+                .into_iter()
+                .flatten()
+                .filter_map(|value| value.as_str()),
+        )
+        .map(String::from);
 
         let form: HashMap<String, String> =
             serde_urlencoded::from_str::<HashMap<String, String>>(self.querystring())
                 .unwrap_or_default();
 
-        crate::template::template_hydrate(ip, path, form)
+        crate::template::template_hydrate(client.as_deref(), path, form, self.htmx(), params)
+    }
+
+    fn htmx(&self) -> crate::Htmx {
+        crate::Htmx::from_headers(|name| self.headers().get_str(name))
+    }
+
+    async fn typed_template<F: DeserializeOwned>(&mut self) -> Result<Template<F>, crate::Error> {
+        self.template().await.deserialize()
+    }
+
+    async fn validated_template<F: DeserializeOwned + crate::Validate>(
+        &mut self,
+    ) -> Result<Template<F>, crate::Invalid> {
+        self.template().await.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_line_of_an_event_is_framed_as_data() {
+        let event = crate::sse::Event {
+            name: "todo".to_string(),
+            data: "<li>\n  Write tests\n</li>".to_string(),
+        };
+        assert_eq!(
+            encode_event(&event),
+            "event: todo\ndata: <li>\ndata:   Write tests\ndata: </li>\n\n"
+        );
     }
 }
@@ -1,10 +1,118 @@
-const socket = new WebSocket(`ws://${location.host}/hmr`);
+// identifies this tab, so that the server replays the forms this tab sent, and no other tab's
+const hmrClient = Math.random().toString(36).slice(2) + Date.now().toString(36);
+const socket = new WebSocket(`ws://${location.host}/hmr?client=${hmrClient}`);
+
+document.addEventListener("htmx:configRequest", (event) => {
+	// @ts-ignore
+	event.detail.headers["HMR-Client"] = hmrClient;
+});
+
+// requests htmx doesn't make, like submitting a plain form, carry the id in a cookie instead.
+// every tab shares the cookie, so it's claimed by whichever tab the user is interacting with
+function claimHmrCookie() {
+	document.cookie = `hyro-client=${hmrClient}; path=/; SameSite=Lax`;
+}
+
+claimHmrCookie();
+window.addEventListener("focus", claimHmrCookie);
+window.addEventListener("beforeunload", claimHmrCookie);
+document.addEventListener("visibilitychange", () => {
+	if (document.visibilityState === "visible") {
+		claimHmrCookie();
+	}
+});
+document.addEventListener("click", claimHmrCookie, true);
+document.addEventListener("submit", claimHmrCookie, true);
 
 // i apologize for the rest of this file
 
+// compile errors that haven't been fixed yet, keyed by the file that needs to be saved
+const hmrErrors = {};
+
+function renderErrorOverlay() {
+	document.getElementById("hmr-overlay")?.remove();
+
+	const errors = Object.values(hmrErrors);
+	if (errors.length === 0) {
+		return;
+	}
+
+	const overlay = document.createElement("div");
+	overlay.id = "hmr-overlay";
+	overlay.style.cssText =
+		"position: fixed; inset: 0; z-index: 2147483647; overflow: auto; padding: 4em 1em; background: rgba(0, 0, 0, 0.66); font: 14px/1.5 ui-monospace, monospace;";
+	overlay.addEventListener("click", (event) => {
+		if (event.target === overlay) {
+			overlay.remove();
+		}
+	});
+
+	for (const error of errors) {
+		const panel = document.createElement("div");
+		panel.style.cssText =
+			"position: relative; max-width: 60em; margin: 0 auto 1em; padding: 1.5em 2em; background: #181818; color: #d8d8d8; border-top: 8px solid #ff5555; border-radius: 6px;";
+
+		const close = document.createElement("button");
+		close.textContent = "×";
+		close.title = "Dismiss";
+		close.style.cssText =
+			"position: absolute; top: 0.5em; right: 0.75em; border: none; background: none; color: inherit; font-size: 1.5em; cursor: pointer;";
+		close.addEventListener("click", () => overlay.remove());
+
+		const message = document.createElement("pre");
+		message.style.cssText =
+			"margin: 0 0 1em; color: #ff5555; font-weight: bold; white-space: pre-wrap;";
+		message.textContent = error.message;
+
+		const location = document.createElement("p");
+		location.style.cssText = "margin: 0 0 1em; color: #8888aa;";
+		location.textContent = [error.file, error.line, error.column]
+			.filter((part) => part !== null)
+			.join(":");
+
+		panel.append(close, message, location);
+
+		if (error.excerpt !== null) {
+			const excerpt = document.createElement("pre");
+			excerpt.style.cssText =
+				"margin: 0; padding: 1em; overflow-x: auto; background: #101010;";
+			excerpt.textContent = error.excerpt;
+			panel.append(excerpt);
+		}
+
+		overlay.append(panel);
+	}
+
+	(document.body ?? document.documentElement).append(overlay);
+}
+
 socket.addEventListener("message", async (event) => {
 	if (typeof event.data === "string") {
-		if (event.data !== "you up?") {
+		if (event.data.startsWith("{")) {
+			const message = JSON.parse(event.data);
+			if (message.asset) {
+				// Scripts can't be swapped out once they've run, so the page is reloaded instead.
+				if (document.querySelector(`script[src^="${message.asset}"]`)) {
+					location.reload();
+				}
+
+				for (const element of document.querySelectorAll(
+					`[src^="${message.asset}"], link[href^="${message.asset}"]`
+				)) {
+					const attribute = element.hasAttribute("src") ? "src" : "href";
+					element.setAttribute(attribute, `${message.asset}?hmr=${Date.now()}`);
+				}
+				return;
+			}
+
+			if (message.error) {
+				hmrErrors[message.error.id] = message.error;
+			} else {
+				delete hmrErrors[message.fixed];
+			}
+
+			renderErrorOverlay();
+		} else if (event.data !== "you up?") {
 			const elements = document.querySelectorAll(`[hmr-path="${event.data}"]`);
 			const indexes = new Uint32Array(elements.length);
 			let i = 0;
@@ -21,7 +129,14 @@ socket.addEventListener("message", async (event) => {
 			socket.send("c");
 			socket.send(indexes);
 			for (const element of elements) {
-				const response = await fetch(event.data).then((res) => res.text());
+				// Blocks are requested the same way modules request them, so only the block is rendered.
+				const block = element.getAttribute("hmr-block");
+				const headers = block
+					? { "HX-Request": "true", "Hyro-Block": block, "HMR-Client": hmrClient }
+					: { "HMR-Client": hmrClient };
+				// Routes with parameters are requested at the URL the element was rendered for.
+				const url = element.getAttribute("hmr-url") ?? event.data;
+				const response = await fetch(url, { headers }).then((res) => res.text());
 				element.outerHTML = response;
 				if (response.includes("hx-")) {
 					// @ts-ignore
@@ -38,11 +153,16 @@ socket.addEventListener("message", async (event) => {
 				});
 		}
 	} else if (event.data.size === 1) {
-		document.querySelector(
-			"head > :is(link[href='/main.css'], style#hmr)"
-		).outerHTML = `<style id="hmr">\n${await fetch("/main.css").then((res) =>
-			res.text()
-		)}\n</style>`;
+		// Every stylesheet served by the app is reloaded, since any of them may be a bundle.
+		const styles = document.querySelectorAll(
+			"head > :is(link[rel=stylesheet][href^='/'], style[hmr-href])"
+		);
+		for (const style of styles) {
+			const href =
+				style.getAttribute("hmr-href") ?? style.getAttribute("href");
+			const css = await fetch(href).then((res) => res.text());
+			style.outerHTML = `<style hmr-href="${href}">\n${css}\n</style>`;
+		}
 	}
 });
 
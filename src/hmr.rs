@@ -1,29 +1,127 @@
-use std::collections::VecDeque;
-use std::net::IpAddr;
+use std::collections::{HashMap, VecDeque};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
 
-pub use minijinja;
-
+use minijinja::machinery::ast;
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use once_cell::sync::Lazy;
 
 use crate::framework::*;
-use crate::render::reload_template;
+use crate::overlay::{fixed_json, CompileError, ERRORS};
+use crate::render::{reload_template, RENDERED_AS};
 use crate::runtime::*;
-use crate::style::STYLE_MAIN_FILE;
+use crate::style::{transform_bundle, BUNDLES};
 use crate::{endpointof, template_dir, template_extension, TEMPLATES};
 
 pub(crate) static PWD: Lazy<PathBuf> =
     Lazy::new(|| std::env::current_dir().unwrap().join(template_dir()));
 
-static HMR_BROADCAST: Lazy<(BroadcastSender<String>, BroadcastReceiver<String>)> =
-    Lazy::new(|| broadcast_channel(1));
+#[derive(Debug, Clone)]
+enum HmrMessage {
+    /// A batch of changed files, so that a single save can refresh
+    /// every template that depends on the file that was saved.
+    Reload(Vec<String>),
+    /// Shown in the browser's error overlay until the file is fixed.
+    Error(CompileError),
+    /// A file that previously failed to compile was saved without errors.
+    Fixed(String),
+    /// The URL of an asset that changed, reloaded wherever the page uses it.
+    Asset(String),
+}
+
+static HMR_BROADCAST: Lazy<(BroadcastSender<HmrMessage>, BroadcastReceiver<HmrMessage>)> =
+    Lazy::new(|| broadcast_channel(16));
 
 static CONNECTIONS: AtomicU8 = AtomicU8::new(0);
 
-pub(crate) async fn hmr_handler(mut socket: WebSocket, ip: IpAddr) {
+/// How long the form history of a tab without an HMR connection is kept after it last rendered.
+const CLOSED_CLIENT_HISTORY: Duration = Duration::from_secs(60);
+
+/// Forgets a tab's form history once its HMR connection closes, since the tab was either closed
+/// or reloaded, which gives it a new client id.
+struct ClientHistory(String);
+
+impl ClientHistory {
+    fn open(client: String) -> Self {
+        TEMPLATES.clients.lock().insert(client.clone());
+        forget_closed_clients();
+        Self(client)
+    }
+}
+
+impl Drop for ClientHistory {
+    fn drop(&mut self) {
+        TEMPLATES.clients.lock().remove(&self.0);
+        TEMPLATES.forms.lock().remove(&self.0);
+        forget_closed_clients();
+    }
+}
+
+/// Forgets tabs that rendered something without ever holding an HMR connection, like a request
+/// that was still in flight when its tab closed.
+fn forget_closed_clients() {
+    let clients = TEMPLATES.clients.lock();
+    TEMPLATES.forms.lock().retain(|client, history| {
+        clients.contains(client) || history.lock().last_render.elapsed() < CLOSED_CLIENT_HISTORY
+    });
+}
+
+/// The client id that `hmr.js` connects with, e.g. `/hmr?client=k3j9x`.
+pub(crate) fn client_of_query(query: &str) -> String {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "client")
+        .map(|(_, client)| client.into_owned())
+        .unwrap_or_default()
+}
+
+/// What HMR is keeping track of, served at `/hmr/state`: the forms each tab will have replayed
+/// when an endpoint is re-rendered, by `hmr-index`, and the files that fail to compile.
+pub(crate) fn state_json() -> String {
+    let clients = TEMPLATES
+        .forms
+        .lock()
+        .iter()
+        .map(|(client, history)| {
+            let endpoints = history
+                .lock()
+                .endpoints
+                .iter()
+                .map(|(endpoint, form_history)| {
+                    let forms = (form_history.first..)
+                        .zip(&form_history.contents)
+                        .map(|(index, form)| serde_json::json!({ "index": index, "form": form }))
+                        .collect::<Vec<_>>();
+
+                    let state = serde_json::json!({
+                        "evicted": form_history.first,
+                        "forms": forms,
+                        "pending": form_history.indexes,
+                    });
+                    (endpoint.clone(), state)
+                })
+                .collect::<serde_json::Map<_, _>>();
+            (client.clone(), serde_json::Value::Object(endpoints))
+        })
+        .collect::<serde_json::Map<_, _>>();
+
+    let errors = ERRORS
+        .lock()
+        .values()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "connections": CONNECTIONS.load(Ordering::SeqCst),
+        "clients": clients,
+        "errors": errors,
+    })
+    .to_string()
+}
+
+pub(crate) async fn hmr_handler(mut socket: WebSocket, client: String) {
     let conn_id = CONNECTIONS.fetch_add(1, Ordering::SeqCst);
+    let client = ClientHistory::open(client);
 
     // It took me. an hour. to find out this single line was breaking HMR.
     // this is its grave.
@@ -32,97 +130,157 @@ pub(crate) async fn hmr_handler(mut socket: WebSocket, ip: IpAddr) {
     #[allow(unused_mut)]
     let mut rx = broadcast_subscribe(&HMR_BROADCAST);
 
-    while let Ok(path) = rx.recv().await {
-        let mut dur_start = instant_now();
-        if socket
-            .send(websocket_message_text("you up?".into()))
+    let errors = ERRORS
+        .lock()
+        .values()
+        .map(CompileError::to_json)
+        .collect::<Vec<_>>();
+
+    for error in errors {
+        socket
+            .send(websocket_message_text(error))
             .await
-            .is_err()
-        {
-            background!(
-                "(HMR) {} CONN{} (browser status)   connection closed",
-                path,
-                conn_id
-            );
+            .unwrap_or_default();
+    }
+
+    loop {
+        // The browser only sends anything in reply to a reload, so until then, waiting on it only
+        // notices when the tab is closed, instead of on the next save. Falling behind on reloads
+        // skips the ones that were missed, rather than closing the connection.
+        let message = race(broadcast_recv(&mut rx), async {
+            while let Some(Ok(_)) = websocket_recv(&mut socket).await {}
+            None
+        })
+        .await;
+
+        let Some(message) = message else {
+            background!("(HMR) CONN{} (browser status)   connection closed", conn_id);
             CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
             return;
-        }
-
-        if path.ends_with(template_extension()) {
-            let endpoint = format!("/{}", endpointof(&path).unwrap());
-
-            socket
-                .send(websocket_message_text(endpoint.clone()))
-                .await
-                .unwrap_or_default();
-
-            let mut escape = false;
-            if let Some(Ok(msg)) = websocket_recv(&mut socket).await {
-                if let Some(t) = websocket_try_as_text(msg) {
-                    if t == "r" {
-                        escape = true;
-                    }
-                }
+        };
+
+        let paths = match message {
+            HmrMessage::Reload(paths) => paths,
+            HmrMessage::Error(error) => {
+                socket
+                    .send(websocket_message_text(error.to_json()))
+                    .await
+                    .unwrap_or_default();
+                continue;
             }
+            HmrMessage::Fixed(id) => {
+                socket
+                    .send(websocket_message_text(fixed_json(&id)))
+                    .await
+                    .unwrap_or_default();
+                continue;
+            }
+            HmrMessage::Asset(url) => {
+                socket
+                    .send(websocket_message_text(
+                        serde_json::json!({ "asset": url }).to_string(),
+                    ))
+                    .await
+                    .unwrap_or_default();
+                continue;
+            }
+        };
 
-            background!(
-                "(HMR) {} CONN{} (browser status)   took {:?}",
-                path,
-                conn_id,
-                dur_start.elapsed()
-            );
-
-            if escape {
+        for path in paths {
+            let mut dur_start = instant_now();
+            if socket
+                .send(websocket_message_text("you up?".into()))
+                .await
+                .is_err()
+            {
                 background!(
-                    "(HMR) {} CONN{} (browser status)   performing full reload",
+                    "(HMR) {} CONN{} (browser status)   connection closed",
                     path,
                     conn_id
                 );
                 CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
-                socket.close().await.unwrap();
                 return;
             }
 
-            dur_start = instant_now();
+            if path.ends_with(template_extension()) {
+                let endpoint = format!("/{}", endpointof(&path).unwrap());
 
-            if let Some(Ok(msg)) = websocket_recv(&mut socket).await {
-                if let Some(b) = websocket_try_as_binary(msg) {
-                    let forms = TEMPLATES.forms.lock();
-                    let mut ip_endpoint_history = forms.get(&ip).unwrap().lock();
+                socket
+                    .send(websocket_message_text(endpoint.clone()))
+                    .await
+                    .unwrap_or_default();
 
-                    ip_endpoint_history.get_mut(&endpoint).unwrap().indexes = b
-                        .chunks(4)
-                        .map(<[u8; 4] as TryFrom<&[u8]>>::try_from)
-                        .map(Result::unwrap)
-                        .map(u32::from_le_bytes)
-                        .collect::<VecDeque<_>>();
+                let mut escape = false;
+                if let Some(Ok(msg)) = websocket_recv(&mut socket).await {
+                    if let Some(t) = websocket_try_as_text(msg) {
+                        if t == "r" {
+                            escape = true;
+                        }
+                    }
                 }
-            }
 
-            background!(
-                "(HMR) {} CONN{} (server form sync) took {:?}",
-                path,
-                conn_id,
-                dur_start.elapsed()
-            );
-        } else {
-            socket
-                .send(websocket_message_binary(vec![0]))
-                .await
-                .unwrap_or_default();
+                background!(
+                    "(HMR) {} CONN{} (browser status)   took {:?}",
+                    path,
+                    conn_id,
+                    dur_start.elapsed()
+                );
+
+                if escape {
+                    background!(
+                        "(HMR) {} CONN{} (browser status)   performing full reload",
+                        path,
+                        conn_id
+                    );
+                    CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+                    socket.close().await.unwrap();
+                    return;
+                }
+
+                dur_start = instant_now();
+
+                if let Some(Ok(msg)) = websocket_recv(&mut socket).await {
+                    if let Some(b) = websocket_try_as_binary(msg) {
+                        let forms = TEMPLATES.forms.lock();
+                        let mut client_history = forms.get(&client.0).map(|h| h.lock());
+
+                        // Tabs that haven't rendered anything through htmx yet, and templates that
+                        // have only been rendered through another template (like a layout), don't
+                        // have any form history to sync.
+                        if let Some(form_history) = client_history
+                            .as_mut()
+                            .and_then(|history| history.endpoints.get_mut(&endpoint))
+                        {
+                            form_history.indexes = b
+                                .chunks(4)
+                                .map(<[u8; 4] as TryFrom<&[u8]>>::try_from)
+                                .map(Result::unwrap)
+                                .map(u32::from_le_bytes)
+                                .collect::<VecDeque<_>>();
+                        }
+                    }
+                }
+
+                background!(
+                    "(HMR) {} CONN{} (server form sync) took {:?}",
+                    path,
+                    conn_id,
+                    dur_start.elapsed()
+                );
+            } else {
+                socket
+                    .send(websocket_message_binary(vec![0]))
+                    .await
+                    .unwrap_or_default();
+            }
         }
     }
-
-    background!("(HMR): Closed");
-    socket.close().await.unwrap();
 }
 
 async fn async_watch<P: AsRef<Path> + std::fmt::Debug>(watch_path: P) -> notify::Result<()> {
     let (mut watcher, mut rx) = async_watcher()?;
     watcher.watch(watch_path.as_ref(), RecursiveMode::Recursive)?;
 
-    let parent_path = watch_path.as_ref().parent().unwrap();
-
     while let Some(res) = rx.next().await {
         match res {
             Ok(Event {
@@ -132,10 +290,51 @@ async fn async_watch<P: AsRef<Path> + std::fmt::Debug>(watch_path: P) -> notify:
             }) => {
                 for path in paths {
                     let dur_start = instant_now();
-                    let relative_path = path.strip_prefix(parent_path).unwrap();
+                    let mut changed = Vec::new();
+                    let mut compiled = None;
+
+                    if let Ok(name) = path.strip_prefix(&*PWD) {
+                        let name = name.display().to_string();
+                        let dependents = dependents_of(&name);
+
+                        if name.ends_with(template_extension()) || dependents.len() > 1 {
+                            compiled = Some((name.clone(), reload_template(&name)));
+                        }
+
+                        // Endpoints that rendered one of these templates in place of their own
+                        // are reloaded too, as if their own template had changed.
+                        let rendered_as = RENDERED_AS.lock();
+                        let endpoints = dependents
+                            .iter()
+                            .filter_map(|t| rendered_as.get(t))
+                            .flatten()
+                            .map(crate::path_of_endpoint)
+                            .collect::<Vec<_>>();
+                        drop(rendered_as);
+
+                        changed.extend(
+                            dependents
+                                .into_iter()
+                                .filter(|t| t.ends_with(template_extension()))
+                                .chain(endpoints),
+                        );
+                    }
+
                     let path = path.strip_prefix(watch_path.as_ref()).unwrap();
 
-                    reload_template(&relative_path.display().to_string());
+                    // Anything that isn't a template is a stylesheet, which could belong to any bundle.
+                    let mut bundled = Vec::new();
+                    if changed.is_empty() {
+                        let bundles = BUNDLES.read().clone();
+                        bundled.extend(bundles.iter().map(|(_, bundle)| {
+                            (
+                                bundle.path().display().to_string(),
+                                transform_bundle(bundle).map(|_| ()),
+                            )
+                        }));
+
+                        changed.push(path.display().to_string());
+                    }
 
                     background!(
                         "\n(HMR) {}       (template render)  took {:?}",
@@ -143,7 +342,27 @@ async fn async_watch<P: AsRef<Path> + std::fmt::Debug>(watch_path: P) -> notify:
                         dur_start.elapsed()
                     );
 
-                    websocket_unwrap(HMR_BROADCAST.0.send(path.display().to_string())).await;
+                    // Browsers keep showing the last working version until the error is fixed.
+                    let mut failed = false;
+                    for compiled in compiled.into_iter().chain(bundled) {
+                        match compiled {
+                            (_, Err(error)) => {
+                                ERRORS.lock().insert(error.id.clone(), error.clone());
+                                broadcast_send(&HMR_BROADCAST.0, HmrMessage::Error(error));
+                                failed = true;
+                            }
+                            (id, Ok(())) if ERRORS.lock().remove(&id).is_some() => {
+                                broadcast_send(&HMR_BROADCAST.0, HmrMessage::Fixed(id));
+                            }
+                            _ => (),
+                        }
+                    }
+
+                    if failed {
+                        continue;
+                    }
+
+                    broadcast_send(&HMR_BROADCAST.0, HmrMessage::Reload(changed));
                 }
             }
             Err(e) => error!("(HMR): {e:?}"),
@@ -154,6 +373,141 @@ async fn async_watch<P: AsRef<Path> + std::fmt::Debug>(watch_path: P) -> notify:
     Ok(())
 }
 
+/// Returns `name` followed by every template that depends on it, directly or through other
+/// templates, via `{% extends %}`, `{% include %}`, `{% import %}` or `{% from %}`.
+fn dependents_of(name: &str) -> Vec<String> {
+    let graph = dependency_graph();
+    let mut affected = vec![name.to_string()];
+
+    let mut i = 0;
+    while i < affected.len() {
+        for (template, dependencies) in &graph {
+            if dependencies.contains(&affected[i]) && !affected.contains(template) {
+                affected.push(template.clone());
+            }
+        }
+        i += 1;
+    }
+
+    affected
+}
+
+/// Maps every template in the template directory to the templates it references by name.
+/// Templates that fail to parse are skipped, since they can't be rendered anyways.
+fn dependency_graph() -> HashMap<String, Vec<String>> {
+    walkdir::WalkDir::new(template_dir())
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|i| i.path().is_file())
+        .filter_map(|entry| {
+            let name = entry
+                .path()
+                .strip_prefix(template_dir())
+                .ok()?
+                .display()
+                .to_string();
+            let source = std::fs::read_to_string(entry.path()).ok()?;
+            let ast = minijinja::machinery::parse(&source, &name).ok()?;
+
+            let mut dependencies = Vec::new();
+            collect_dependencies(&ast, &mut dependencies);
+            Some((name, dependencies))
+        })
+        .collect()
+}
+
+fn collect_dependencies(stmt: &ast::Stmt, dependencies: &mut Vec<String>) {
+    let mut collect_body = |body: &[ast::Stmt]| {
+        body.iter()
+            .for_each(|stmt| collect_dependencies(stmt, dependencies))
+    };
+
+    match stmt {
+        ast::Stmt::Template(t) => collect_body(&t.children),
+        ast::Stmt::ForLoop(f) => {
+            collect_body(&f.body);
+            collect_body(&f.else_body);
+        }
+        ast::Stmt::IfCond(i) => {
+            collect_body(&i.true_body);
+            collect_body(&i.false_body);
+        }
+        ast::Stmt::WithBlock(w) => collect_body(&w.body),
+        ast::Stmt::SetBlock(s) => collect_body(&s.body),
+        ast::Stmt::AutoEscape(a) => collect_body(&a.body),
+        ast::Stmt::FilterBlock(f) => collect_body(&f.body),
+        ast::Stmt::Block(b) => collect_body(&b.body),
+        ast::Stmt::Macro(m) => collect_body(&m.body),
+        ast::Stmt::CallBlock(c) => collect_body(&c.macro_decl.body),
+        ast::Stmt::Extends(e) => collect_names(&e.name, dependencies),
+        ast::Stmt::Include(i) => collect_names(&i.name, dependencies),
+        ast::Stmt::Import(i) => collect_names(&i.expr, dependencies),
+        ast::Stmt::FromImport(f) => collect_names(&f.expr, dependencies),
+        ast::Stmt::EmitExpr(e) => collect_inline_module(&e.expr, dependencies),
+        _ => (),
+    }
+}
+
+/// Components and inline modules are rendered as part of the page, so the page depends on their
+/// templates.
+fn collect_inline_module(expr: &ast::Expr, dependencies: &mut Vec<String>) {
+    let ast::Expr::Call(call) = expr else {
+        return;
+    };
+    let (ast::Expr::Var(var), Some(ast::Expr::Const(name))) = (&call.expr, call.args.first())
+    else {
+        return;
+    };
+    let Some(name) = name.value.as_str() else {
+        return;
+    };
+    if var.id == "component" {
+        dependencies.push(crate::path_of_endpoint(name));
+        return;
+    } else if var.id != "module" {
+        return;
+    }
+
+    let kwargs = call.args.iter().find_map(|arg| match arg {
+        ast::Expr::Kwargs(kwargs) => Some(&kwargs.pairs),
+        _ => None,
+    });
+    let kwarg = |key| {
+        kwargs
+            .and_then(|pairs| pairs.iter().find(|(k, _)| *k == key))
+            .map(|(_, v)| v)
+    };
+
+    let inline = match kwarg("inline") {
+        Some(ast::Expr::Const(c)) => Some(c.value.is_true()),
+        _ => None,
+    };
+    let deferred = ["trigger", "swap", "tag", "placeholder", "error"]
+        .iter()
+        .any(|key| kwarg(key).is_some());
+
+    let name = crate::path_of_endpoint(name);
+    if crate::render::is_inline(&name, inline, deferred) {
+        dependencies.push(name);
+    }
+}
+
+/// Only constant names (or lists of them, for `{% include [...] %}`) can be resolved ahead of time.
+fn collect_names(expr: &ast::Expr, dependencies: &mut Vec<String>) {
+    match expr {
+        ast::Expr::Const(c) => {
+            if let Some(name) = c.value.as_str() {
+                dependencies.push(name.to_string());
+            }
+        }
+        ast::Expr::List(l) => l
+            .items
+            .iter()
+            .for_each(|item| collect_names(item, dependencies)),
+        _ => (),
+    }
+}
+
 pub(crate) fn watch_templates() {
     spawn(async {
         if let Err(e) = async_watch(&*PWD).await {
@@ -162,22 +516,116 @@ pub(crate) fn watch_templates() {
     });
 }
 
-pub(crate) fn watch_style() {
-    spawn(async {
-        let style_file = std::env::current_dir()
-            .unwrap()
-            .join(STYLE_MAIN_FILE.get().unwrap());
+/// Watches the directory of a bundled stylesheet, or a directory of CSS modules, unless it's
+/// already watched along with the templates or another stylesheet.
+pub(crate) fn watch_style(path: &Path) {
+    static WATCHED: Lazy<parking_lot::Mutex<Vec<PathBuf>>> = Lazy::new(Default::default);
+
+    let path = std::env::current_dir().unwrap().join(path);
+    let style_path = if path.is_dir() {
+        path
+    } else {
+        path.parent().unwrap().to_path_buf()
+    };
+
+    let mut watched = WATCHED.lock();
+    if style_path.starts_with(&*PWD) || watched.iter().any(|w| style_path.starts_with(w)) {
+        return;
+    }
+    watched.push(style_path.clone());
+
+    spawn(async move {
+        if let Err(e) = async_watch(&style_path).await {
+            error!("(HMR): {e}");
+        }
+    });
+}
 
-        let style_path = style_file.parent().unwrap();
+/// Watches a directory of assets served at `endpoint`.
+pub(crate) fn watch_assets(endpoint: &str, dir: &Path) {
+    let endpoint = endpoint.trim_end_matches('/').to_string();
+    let dir = std::env::current_dir().unwrap().join(dir);
+
+    spawn(async move {
+        let watched = async_watch_dir(&dir, |path| {
+            let name = path.strip_prefix(&dir).ok()?;
+            let url = format!("{endpoint}/{}", name.to_string_lossy().replace('\\', "/"));
+            background!("\n(HMR) {url}       (asset)");
+            Some(vec![HmrMessage::Asset(url)])
+        });
+        if let Err(e) = watched.await {
+            error!("(HMR): {e}");
+        }
+    });
+}
 
-        if style_path.strip_prefix(&*PWD).is_err() {
-            if let Err(e) = async_watch(style_path).await {
-                error!("(HMR): {e}");
+/// Watches the directory of a bundled script, rebuilding it whenever a script inside changes.
+#[cfg(feature = "bundle-js")]
+pub(crate) fn watch_script(endpoint: &str, entry: &Path) {
+    let endpoint = endpoint.to_string();
+    let entry = entry.to_path_buf();
+    let dir = std::env::current_dir()
+        .unwrap()
+        .join(entry.parent().unwrap_or(Path::new("")));
+
+    spawn(async move {
+        let watched = async_watch_dir(&dir, |path| {
+            let extension = path.extension()?.to_str()?;
+            if !crate::script::EXTENSIONS.contains(&extension) {
+                return None;
             }
+
+            let dur_start = instant_now();
+            let bundled = crate::script::transform_script(&entry);
+            background!(
+                "\n(HMR) {}       (script bundle)    took {:?}",
+                path.display(),
+                dur_start.elapsed()
+            );
+
+            // Browsers keep running the last working version until the error is fixed.
+            let id = entry.display().to_string();
+            Some(match bundled {
+                Err(error) => vec![HmrMessage::Error(error)],
+                Ok(_) if ERRORS.lock().remove(&id).is_some() => {
+                    vec![HmrMessage::Fixed(id), HmrMessage::Asset(endpoint.clone())]
+                }
+                Ok(_) => vec![HmrMessage::Asset(endpoint.clone())],
+            })
+        });
+        if let Err(e) = watched.await {
+            error!("(HMR): {e}");
         }
     });
 }
 
+/// Watches `dir`, broadcasting whatever `on_change` returns for each file that changes inside.
+async fn async_watch_dir(
+    dir: &Path,
+    mut on_change: impl FnMut(PathBuf) -> Option<Vec<HmrMessage>>,
+) -> notify::Result<()> {
+    let (mut watcher, mut rx) = async_watcher()?;
+    watcher.watch(dir, RecursiveMode::Recursive)?;
+
+    while let Some(res) = rx.next().await {
+        match res {
+            Ok(Event {
+                kind: EventKind::Access(_),
+                paths,
+                ..
+            }) => {
+                for message in paths.into_iter().filter_map(&mut on_change).flatten() {
+                    broadcast_send(&HMR_BROADCAST.0, message);
+                }
+            }
+            Err(e) => error!("(HMR): {e:?}"),
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
 fn async_watcher() -> notify::Result<(RecommendedWatcher, MpscReceiver<notify::Result<Event>>)> {
     #[allow(unused_mut)]
     let (mut tx, rx) = mpsc_channel(1);
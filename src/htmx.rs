@@ -0,0 +1,202 @@
+use serde::Serialize;
+
+/// The htmx request headers a template was requested with. Available to templates as `htmx`,
+/// so a single template can render a full page or a fragment:
+///
+/// ```jinja
+/// {% if not htmx.request %}{% include "_header.html.jinja2" %}{% endif %}
+/// ```
+///
+/// With `framework-axum` this can also be extracted on its own. With `framework-trillium`,
+/// use `ConnExt::htmx`.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Htmx {
+    /// `HX-Request`: the request was made by htmx.
+    pub request: bool,
+    /// `HX-Boosted`: the request was made by an element using `hx-boost`.
+    pub boosted: bool,
+    /// `HX-History-Restore-Request`: htmx is restoring a page that's missing from its history cache.
+    pub history_restore_request: bool,
+    /// `HX-Current-URL`: the URL of the browser when the request was made.
+    pub current_url: Option<String>,
+    /// `HX-Prompt`: the user's response to an `hx-prompt`.
+    pub prompt: Option<String>,
+    /// `HX-Target`: the `id` of the target element, if it has one.
+    pub target: Option<String>,
+    /// `HX-Trigger`: the `id` of the triggered element, if it has one.
+    pub trigger: Option<String>,
+    /// `HX-Trigger-Name`: the `name` of the triggered element, if it has one.
+    pub trigger_name: Option<String>,
+    /// `Hyro-Block`: the `{% block %}` of the template to render, sent by modules with a `block`.
+    /// Other elements can ask for one with `hx-headers='{"Hyro-Block": "sidebar"}'`.
+    pub block: Option<String>,
+}
+
+impl Htmx {
+    /// Whether htmx is swapping part of the page, rather than navigating to a new one.
+    pub fn is_partial(&self) -> bool {
+        self.request && !self.boosted && !self.history_restore_request
+    }
+
+    pub(crate) fn from_headers<'a, H: Fn(&str) -> Option<&'a str>>(header: H) -> Self {
+        let flag = |name| header(name) == Some("true");
+        let text = |name| header(name).map(String::from);
+
+        Self {
+            request: flag("HX-Request"),
+            boosted: flag("HX-Boosted"),
+            history_restore_request: flag("HX-History-Restore-Request"),
+            current_url: text("HX-Current-URL"),
+            prompt: text("HX-Prompt"),
+            target: text("HX-Target"),
+            trigger: text("HX-Trigger"),
+            trigger_name: text("HX-Trigger-Name"),
+            block: text("Hyro-Block"),
+        }
+    }
+}
+
+/// htmx response headers, which tell htmx what to do with the response.
+///
+/// With `framework-axum` this can be returned alongside a body, e.g.
+/// `(HtmxResponse::new().trigger("saved"), template.render(context!()))`. With
+/// `framework-trillium`, run it as a handler before setting the body.
+#[derive(Debug, Default, Clone)]
+pub struct HtmxResponse {
+    headers: Vec<(&'static str, String)>,
+}
+
+impl HtmxResponse {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `HX-Redirect`: navigates the browser to `url`, with a full page reload.
+    pub fn redirect<S: Into<String>>(self, url: S) -> Self {
+        self.with("HX-Redirect", url)
+    }
+
+    /// `HX-Location`: navigates to `url` without a full page reload, like a boosted link.
+    pub fn location<S: Into<String>>(self, url: S) -> Self {
+        self.with("HX-Location", url)
+    }
+
+    /// `HX-Refresh`: reloads the page.
+    pub fn refresh(self) -> Self {
+        self.with("HX-Refresh", "true")
+    }
+
+    /// `HX-Push-Url`: pushes `url` onto the browser's history.
+    pub fn push_url<S: Into<String>>(self, url: S) -> Self {
+        self.with("HX-Push-Url", url)
+    }
+
+    /// `HX-Replace-Url`: replaces the current URL in the browser's history.
+    pub fn replace_url<S: Into<String>>(self, url: S) -> Self {
+        self.with("HX-Replace-Url", url)
+    }
+
+    /// `HX-Trigger`: triggers events on the client once the response is received.
+    /// Either an event name, a comma-separated list of names, or a JSON object of events and their details.
+    pub fn trigger<S: Into<String>>(self, events: S) -> Self {
+        self.with("HX-Trigger", events)
+    }
+
+    /// `HX-Trigger-After-Swap`: like [HtmxResponse::trigger], once the response has been swapped in.
+    pub fn trigger_after_swap<S: Into<String>>(self, events: S) -> Self {
+        self.with("HX-Trigger-After-Swap", events)
+    }
+
+    /// `HX-Trigger-After-Settle`: like [HtmxResponse::trigger], once the response has settled.
+    pub fn trigger_after_settle<S: Into<String>>(self, events: S) -> Self {
+        self.with("HX-Trigger-After-Settle", events)
+    }
+
+    /// `HX-Reswap`: overrides how the response is swapped in, like `hx-swap`.
+    pub fn reswap<S: Into<String>>(self, swap: S) -> Self {
+        self.with("HX-Reswap", swap)
+    }
+
+    /// `HX-Retarget`: a CSS selector for the element the response is swapped into, instead of the target.
+    pub fn retarget<S: Into<String>>(self, selector: S) -> Self {
+        self.with("HX-Retarget", selector)
+    }
+
+    /// `HX-Reselect`: a CSS selector for the part of the response that's swapped in, like `hx-select`.
+    pub fn reselect<S: Into<String>>(self, selector: S) -> Self {
+        self.with("HX-Reselect", selector)
+    }
+
+    pub(crate) fn headers(&self) -> &[(&'static str, String)] {
+        &self.headers
+    }
+
+    fn with<S: Into<String>>(mut self, name: &'static str, value: S) -> Self {
+        self.headers.retain(|(n, _)| *n != name);
+        self.headers.push((name, value.into()));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn htmx_of(headers: &[(&str, &'static str)]) -> Htmx {
+        Htmx::from_headers(|name| {
+            headers
+                .iter()
+                .find(|(header, _)| *header == name)
+                .map(|(_, value)| *value)
+        })
+    }
+
+    #[test]
+    fn request_headers_are_read() {
+        let htmx = htmx_of(&[
+            ("HX-Request", "true"),
+            ("HX-Target", "todo-list"),
+            ("HX-Trigger", "todo-form"),
+            ("HX-Trigger-Name", "description"),
+        ]);
+        assert!(htmx.request);
+        assert!(htmx.is_partial());
+        assert_eq!(htmx.target.as_deref(), Some("todo-list"));
+        assert_eq!(htmx.trigger.as_deref(), Some("todo-form"));
+        assert_eq!(htmx.trigger_name.as_deref(), Some("description"));
+        assert_eq!(htmx.current_url, None);
+    }
+
+    #[test]
+    fn navigation_is_not_partial() {
+        assert!(!htmx_of(&[]).request);
+        assert!(!htmx_of(&[]).is_partial());
+        assert!(!htmx_of(&[("HX-Request", "false")]).request);
+        assert!(!htmx_of(&[("HX-Request", "true"), ("HX-Boosted", "true")]).is_partial());
+        assert!(!htmx_of(&[
+            ("HX-Request", "true"),
+            ("HX-History-Restore-Request", "true")
+        ])
+        .is_partial());
+    }
+
+    #[test]
+    fn response_headers_are_set_once() {
+        let response = HtmxResponse::new()
+            .redirect("/login")
+            .trigger("saved")
+            .trigger(r#"{"saved": {"id": 1}}"#);
+
+        assert_eq!(
+            response.headers(),
+            [
+                ("HX-Redirect", "/login".to_string()),
+                ("HX-Trigger", r#"{"saved": {"id": 1}}"#.to_string()),
+            ]
+        );
+        assert_eq!(
+            HtmxResponse::new().refresh().headers(),
+            [("HX-Refresh", "true".to_string())]
+        );
+    }
+}
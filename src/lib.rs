@@ -5,28 +5,50 @@ mod log;
 
 #[cfg(debug_assertions)]
 mod hmr;
+#[cfg(debug_assertions)]
+mod overlay;
 
+mod asset;
+mod component;
 pub mod config;
+mod embedded;
+mod error;
 mod framework;
+mod htmx;
 mod render;
 mod runtime;
+#[cfg(feature = "bundle-js")]
+mod script;
+mod socket;
+mod sse;
 pub mod style;
 mod template;
+mod validate;
 pub use framework::prelude;
+pub use framework::StreamedTemplate;
 
 #[cfg(debug_assertions)]
 use std::collections::VecDeque;
 
 #[cfg(debug_assertions)]
 use parking_lot::Mutex;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 
+pub use component::{component, ComponentContext};
+pub use error::Error;
+pub use htmx::{Htmx, HtmxResponse};
+#[cfg(feature = "embed")]
+pub use hyro_macros::embed;
 pub use minijinja::context as _ctx;
 use once_cell::sync::{Lazy, OnceCell};
+pub use socket::{broadcast, Socket, SocketMessage};
+pub use sse::publish;
 use std::net::TcpListener;
 pub use template::*;
+pub use validate::*;
 
 pub mod reexports {
     pub use lightningcss;
@@ -38,6 +60,14 @@ pub fn _empty_context() -> minijinja::value::Value {
     minijinja::value::Value::UNDEFINED
 }
 
+#[doc(hidden)]
+pub fn _embed(
+    files: &'static [(&'static str, &'static [u8])],
+    bundles: &'static [(&'static str, &'static str)],
+) {
+    embedded::set(files, bundles);
+}
+
 #[macro_export]
 macro_rules! context {
     () => {
@@ -88,18 +118,64 @@ pub fn bind(addr: &'static str) -> TcpListener {
 #[cfg(debug_assertions)]
 type DB<T, U> = Mutex<HashMap<T, Mutex<U>>>;
 
+/// Forms kept per tab and endpoint. Elements polled by htmx are rendered over and over, so only
+/// the forms of the most recently rendered elements are kept.
+#[cfg(debug_assertions)]
+const FORM_HISTORY: usize = 128;
+
 #[cfg(debug_assertions)]
 #[derive(Debug, Default, Clone)]
 pub(crate) struct TemplateFormData {
-    pub contents: Vec<HashMap<String, String>>,
+    /// The form of each element rendered, oldest first, starting from the element at `first`.
+    pub contents: VecDeque<HashMap<String, String>>,
+    /// The `hmr-index` of the oldest element whose form is still kept.
+    pub first: u32,
     pub indexes: VecDeque<u32>,
 }
 
+#[cfg(debug_assertions)]
+impl TemplateFormData {
+    /// The form of the element at `index`, unless it has been evicted.
+    pub fn get(&self, index: u32) -> Option<&HashMap<String, String>> {
+        self.contents.get(index.checked_sub(self.first)? as usize)
+    }
+
+    /// Records the form of a newly rendered element, evicting the oldest form once full.
+    pub fn push(&mut self, form: HashMap<String, String>) {
+        if self.contents.len() == FORM_HISTORY {
+            self.contents.pop_front();
+            self.first += 1;
+        }
+        self.contents.push_back(form);
+    }
+}
+
+#[cfg(debug_assertions)]
+#[derive(Debug)]
+pub(crate) struct ClientForms {
+    pub endpoints: HashMap<String, TemplateFormData>,
+    /// Tabs can render before their HMR connection opens, or after it closes, so the history of a
+    /// tab without a connection is only forgotten once it hasn't rendered anything for a while.
+    pub last_render: std::time::Instant,
+}
+
+#[cfg(debug_assertions)]
+impl Default for ClientForms {
+    fn default() -> Self {
+        Self {
+            endpoints: HashMap::new(),
+            last_render: std::time::Instant::now(),
+        }
+    }
+}
+
 #[cfg(debug_assertions)]
 #[derive(Debug, Default)]
 pub(crate) struct Templates {
-    pub sources: DB<String, String>,
-    pub forms: DB<IpAddr, HashMap<String, TemplateFormData>>,
+    /// Form history keyed by the HMR client id of the browser tab, and then by endpoint.
+    pub forms: DB<String, ClientForms>,
+    /// The client ids of the tabs with an open HMR connection.
+    pub clients: Mutex<std::collections::HashSet<String>>,
 }
 
 #[cfg(debug_assertions)]
@@ -107,57 +183,135 @@ pub(crate) static TEMPLATES: Lazy<Templates> = Lazy::new(Templates::default);
 
 #[cfg(not(debug_assertions))]
 pub(crate) struct TemplateSourceData {
+    /// The path of the template relative to the template directory, which is also
+    /// the name it's registered under in the environment.
+    pub name: String,
     pub source: String,
     pub can_skip_rendering: bool,
 }
 
+/// Lists every template as its path relative to the template directory, and its source.
+/// Templates baked in with `hyro::embed!` take priority over the ones on the file system.
+#[cfg(not(debug_assertions))]
+fn template_files() -> Vec<(String, String)> {
+    embedded::list(template_dir())
+        .into_iter()
+        .filter_map(|name| {
+            let path = template_dir().join(&name);
+            let source = match embedded::get(&path) {
+                Some(source) => source.to_string(),
+                None => std::fs::read_to_string(&path)
+                    .map_err(|e| error!("Error while reading {}: {}", path.display(), e))
+                    .ok()?,
+            };
+            Some((name, source))
+        })
+        .collect()
+}
+
+/// Every template in the template directory, keyed by its name.
 #[cfg(not(debug_assertions))]
 pub(crate) static TEMPLATES: Lazy<HashMap<String, TemplateSourceData>> = Lazy::new(|| {
+    template_files()
+        .into_iter()
+        .map(|(name, t)| {
+            let template = TemplateSourceData {
+                name: name.clone(),
+                can_skip_rendering: !(t.contains("{{")
+                    || t.contains("}}")
+                    || t.contains("{%")
+                    || t.contains("%}")),
+                source: t,
+            };
+            (name, template)
+        })
+        .collect()
+});
+
+/// Lists the name of every template in the template directory.
+#[cfg(not(debug_assertions))]
+pub(crate) fn template_names() -> Vec<String> {
+    TEMPLATES.keys().cloned().collect()
+}
+
+#[cfg(debug_assertions)]
+pub(crate) fn template_names() -> Vec<String> {
     walkdir::WalkDir::new(template_dir())
         .into_iter()
         .filter_map(Result::ok)
         .filter(|i| i.path().is_file())
-        .fold(HashMap::new(), |mut acc, entry| {
-            let dir = entry.path().to_string_lossy().to_string();
-            let t = std::fs::read_to_string(dir).unwrap();
-            acc.insert(
-                format!(
-                    "/{}",
-                    entry
-                        .path()
-                        .strip_prefix(template_dir())
-                        .unwrap()
-                        .to_string_lossy()
-                        .to_string()
-                        .trim_end_matches(template_extension())
-                        .replace("index", "")
-                ),
-                TemplateSourceData {
-                    source: t.clone(),
-                    can_skip_rendering: !(t.contains("{{")
-                        || t.contains("}}")
-                        || t.contains("{%")
-                        || t.contains("%}")),
-                },
-            );
-            acc
+        .filter_map(|entry| {
+            let name = entry.path().strip_prefix(template_dir()).ok()?;
+            Some(name.to_string_lossy().replace('\\', "/"))
         })
-});
+        .collect()
+}
+
+/// The route a template is served at by `with_template_routes`, e.g. `users/[id].html.jinja2` is
+/// served at `/users/:id`. Files and directories starting with `_` are left out, since they're
+/// layouts and partials.
+pub(crate) fn route_of_template(name: &str) -> Option<String> {
+    let segments: Vec<&str> = name
+        .strip_suffix(template_extension())?
+        .split('/')
+        .collect();
+    if segments.iter().any(|segment| segment.starts_with('_')) {
+        return None;
+    }
+
+    let last = segments.len() - 1;
+    Some(
+        segments
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| match param_of_segment(segment) {
+                Some(param) => format!("/:{param}"),
+                None if i == last && *segment == "index" => "/".into(),
+                None => format!("/{segment}"),
+            })
+            .collect(),
+    )
+}
+
+/// `[id]` in a template's path is the `:id` parameter of its route.
+fn param_of_segment(segment: &str) -> Option<&str> {
+    segment.strip_prefix('[')?.strip_suffix(']')
+}
 
 #[cfg(debug_assertions)]
-pub(crate) fn endpointof(path: &str) -> Option<&str> {
+pub(crate) fn endpointof(path: &str) -> Option<String> {
     let without_extension = path.trim_end_matches(template_extension());
-    if without_extension == "/index" {
-        Some("/")
+    let endpoint = if without_extension == "/index" {
+        "/"
     } else if without_extension == "index" {
-        Some("")
+        ""
     } else {
-        Some(without_extension)
-    }
+        without_extension
+    };
+
+    Some(
+        endpoint
+            .split('/')
+            .map(|segment| match param_of_segment(segment) {
+                Some(param) => format!(":{param}"),
+                None => segment.into(),
+            })
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
 }
 
 pub(crate) fn path_of_endpoint<S: AsRef<str>>(endpoint: S) -> String {
-    let endpoint = endpoint.as_ref();
+    let endpoint = endpoint
+        .as_ref()
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(param) => Cow::Owned(format!("[{param}]")),
+            None => Cow::Borrowed(segment),
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    let endpoint = endpoint.as_str();
     format!(
         "{}{}",
         if endpoint.ends_with('/') {
@@ -173,3 +327,107 @@ pub(crate) fn path_of_endpoint<S: AsRef<str>>(endpoint: S) -> String {
         }
     )
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Renders from the fixtures in `tests/templates`. The template directory can only be set
+    /// once, so every test that renders calls this first.
+    pub(crate) fn use_test_templates() {
+        let _ = crate::config::set_template_dir("tests/templates");
+    }
+
+    #[test]
+    fn index_templates_are_routed_at_their_directory() {
+        assert_eq!(route_of_template("index.html.jinja2").as_deref(), Some("/"));
+        assert_eq!(
+            route_of_template("users/index.html.jinja2").as_deref(),
+            Some("/users/")
+        );
+        assert_eq!(
+            route_of_template("users/list.html.jinja2").as_deref(),
+            Some("/users/list")
+        );
+    }
+
+    #[test]
+    fn bracketed_segments_are_route_params() {
+        assert_eq!(
+            route_of_template("users/[id].html.jinja2").as_deref(),
+            Some("/users/:id")
+        );
+        assert_eq!(
+            route_of_template("[org]/[repo]/index.html.jinja2").as_deref(),
+            Some("/:org/:repo/")
+        );
+        assert_eq!(path_of_endpoint("/users/:id"), "users/[id].html.jinja2");
+    }
+
+    #[test]
+    fn routes_and_templates_round_trip() {
+        for name in [
+            "index.html.jinja2",
+            "about.html.jinja2",
+            "users/index.html.jinja2",
+            "users/[id].html.jinja2",
+            "users/[id]/posts/[post].html.jinja2",
+        ] {
+            let route = route_of_template(name).unwrap();
+            assert_eq!(path_of_endpoint(&route), name);
+        }
+    }
+
+    #[test]
+    fn partials_are_not_routed() {
+        assert_eq!(route_of_template("_layout.html.jinja2"), None);
+        assert_eq!(route_of_template("users/_row.html.jinja2"), None);
+        assert_eq!(route_of_template("_partials/row.html.jinja2"), None);
+        assert_eq!(route_of_template("style.css"), None);
+    }
+
+    #[test]
+    fn endpoints_resolve_to_their_templates() {
+        assert_eq!(path_of_endpoint("/"), "index.html.jinja2");
+        assert_eq!(path_of_endpoint("/users/"), "users/index.html.jinja2");
+        assert_eq!(path_of_endpoint("/users"), "users.html.jinja2");
+        assert_eq!(path_of_endpoint("/feed.xml"), "feed.xml");
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn form_history_keeps_the_latest_forms() {
+        let mut history = TemplateFormData::default();
+        for i in 0..FORM_HISTORY + 2 {
+            history.push(HashMap::from([("i".to_string(), i.to_string())]));
+        }
+
+        assert_eq!(history.contents.len(), FORM_HISTORY);
+        assert_eq!(history.first, 2);
+        assert!(history.get(0).is_none());
+        assert!(history.get(1).is_none());
+        assert_eq!(history.get(2).unwrap()["i"], "2");
+
+        let last = FORM_HISTORY as u32 + 1;
+        assert_eq!(history.get(last).unwrap()["i"], last.to_string());
+        assert!(history.get(last + 1).is_none());
+    }
+
+    #[cfg(not(debug_assertions))]
+    #[test]
+    fn embedded_templates_are_merged_with_the_file_system() {
+        use_test_templates();
+        crate::embedded::set(
+            &[
+                ("tests/templates/_embedded.html.jinja2", b"embedded only"),
+                ("tests/templates/_shadowed.html.jinja2", b"from the binary"),
+            ],
+            &[],
+        );
+
+        let files: HashMap<_, _> = template_files().into_iter().collect();
+        assert_eq!(files["_embedded.html.jinja2"], "embedded only");
+        assert_eq!(files["_shadowed.html.jinja2"], "from the binary");
+        assert!(files["greeting.html.jinja2"].contains("Hello"));
+    }
+}
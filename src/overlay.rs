@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_json::json;
+
+#[cfg(feature = "bundle-js")]
+use crate::script::BundleJSError;
+use crate::style::TransformCSSError;
+
+/// Files that currently fail to compile, keyed by the file that needs to be saved to fix them.
+/// New HMR connections receive these right away, so the overlay survives a page reload.
+pub(crate) static ERRORS: Lazy<Mutex<HashMap<String, CompileError>>> = Lazy::new(Default::default);
+
+/// A template, stylesheet or script that failed to compile, as shown in the browser's error overlay.
+#[derive(Debug, Clone)]
+pub(crate) struct CompileError {
+    pub id: String,
+    file: String,
+    line: Option<u32>,
+    column: Option<u32>,
+    message: String,
+    excerpt: Option<String>,
+}
+
+impl CompileError {
+    pub fn template(name: &str, source: &str, e: &minijinja::Error) -> Self {
+        let line = e.line().map(|line| line as u32);
+        let column = e.range().map(|range| {
+            let start = range.start.min(source.len());
+            source[..start].rfind('\n').map_or(start, |i| start - i - 1) as u32 + 1
+        });
+
+        Self {
+            id: name.to_string(),
+            file: name.to_string(),
+            line,
+            column,
+            message: match e.detail() {
+                Some(detail) => format!("{}: {}", e.kind(), detail),
+                None => e.kind().to_string(),
+            },
+            excerpt: line.map(|line| excerpt(source, line, column)),
+        }
+    }
+
+    /// Stylesheets are bundled from a single entrypoint, so errors are keyed by that entrypoint
+    /// (`id`) even when they come from a file it imports.
+    pub fn css(id: &str, e: &TransformCSSError) -> Self {
+        let location = e.location();
+        let line = location.map(|loc| loc.line + 1);
+        let column = location.map(|loc| loc.column);
+
+        Self {
+            id: id.to_string(),
+            file: location.map_or(id.to_string(), |loc| loc.filename.clone()),
+            line,
+            column,
+            message: e.message(),
+            excerpt: location.and_then(|loc| {
+                let source = std::fs::read_to_string(&loc.filename).ok()?;
+                Some(excerpt(&source, line?, column))
+            }),
+        }
+    }
+
+    /// Like stylesheets, scripts are keyed by their entrypoint.
+    #[cfg(feature = "bundle-js")]
+    pub fn js(id: &str, e: &BundleJSError) -> Self {
+        let source = std::fs::read_to_string(&e.path).ok();
+        // Offsets that don't land on a character of the file, like when it changed since it was
+        // bundled, are left out rather than pointing at the wrong place.
+        let position = e.offset.zip(source.as_ref()).and_then(|(offset, source)| {
+            let before = source.get(..offset)?;
+            let line = before.matches('\n').count() as u32 + 1;
+            let column = before
+                .rfind('\n')
+                .map_or(before.len(), |i| before.len() - i - 1) as u32
+                + 1;
+            Some((line, column))
+        });
+
+        Self {
+            id: id.to_string(),
+            file: e.path.display().to_string(),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+            message: e.message.clone(),
+            excerpt: position
+                .zip(source)
+                .map(|((line, column), source)| excerpt(&source, line, Some(column))),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        json!({
+            "error": {
+                "id": self.id,
+                "file": self.file,
+                "line": self.line,
+                "column": self.column,
+                "message": self.message,
+                "excerpt": self.excerpt,
+            }
+        })
+        .to_string()
+    }
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        if let Some(column) = self.column {
+            write!(f, ":{column}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Sent once a file that previously failed to compile is saved without errors.
+pub(crate) fn fixed_json(id: &str) -> String {
+    json!({ "fixed": id }).to_string()
+}
+
+/// Renders the lines surrounding `line`, with a caret pointing at `column`.
+fn excerpt(source: &str, line: u32, column: Option<u32>) -> String {
+    let (line, column) = (line as usize, column.map(|column| column as usize));
+    let first = line.saturating_sub(3);
+    let width = (line + 2).to_string().len();
+    let mut excerpt = String::new();
+
+    for (i, text) in source
+        .lines()
+        .enumerate()
+        .skip(first)
+        .take(line + 2 - first)
+    {
+        let lineno = i + 1;
+        let marker = if lineno == line { '>' } else { ' ' };
+        excerpt += &format!("{marker} {lineno:>width$} | {text}\n");
+
+        if let (true, Some(column)) = (lineno == line, column) {
+            excerpt += &format!("  {:>width$} | {:>column$}\n", "", "^");
+        }
+    }
+
+    excerpt
+}
@@ -1,60 +1,286 @@
-use minijinja::value::{Value, ValueKind};
+use minijinja::value::{Kwargs, Value, ValueKind};
+#[cfg(debug_assertions)]
 use parking_lot::Mutex;
+use parking_lot::RwLock;
 use std::borrow::Cow;
+use std::collections::HashMap;
+#[cfg(debug_assertions)]
+use std::collections::HashSet;
+use std::sync::Arc;
 
 use minijinja::Environment;
-use once_cell::sync::Lazy;
-use tap::Tap;
+use once_cell::sync::{Lazy, OnceCell};
+use tap::{Tap, TapFallible};
 
+use crate::component::Props;
 use crate::framework::*;
-use crate::{path_of_endpoint, template_extension, TEMPLATES};
+use crate::runtime::{mpsc_channel, mpsc_send_blocking, MpscReceiver, MpscSender};
+use crate::Error;
+use crate::{path_of_endpoint, template_extension};
+
+#[cfg(not(debug_assertions))]
+use crate::TEMPLATES;
 
 const HMR_ENABLED: bool = cfg!(debug_assertions);
 
-pub(crate) static ENVIRONMENT: Lazy<Mutex<Environment>> = Lazy::new(|| {
-    Mutex::new(Environment::new().tap_mut(|env| {
+pub(crate) static INLINE_MODULES: OnceCell<bool> = OnceCell::new();
+
+/// The endpoints that have rendered each template in place of their own, so HMR can reload them
+/// when the template changes.
+#[cfg(debug_assertions)]
+pub(crate) static RENDERED_AS: Lazy<Mutex<HashMap<String, HashSet<String>>>> =
+    Lazy::new(Default::default);
+
+/// Templates are compiled once and stored in the environment. Renders work from a snapshot of it,
+/// so that no lock is held while rendering: configuring the environment, or HMR invalidating a
+/// template, swaps in a modified copy for the renders that start afterwards.
+pub(crate) static ENVIRONMENT: Lazy<RwLock<Arc<Environment>>> = Lazy::new(|| {
+    RwLock::new(Arc::new(Environment::new().tap_mut(|env| {
         env.add_global("hmr", HMR_ENABLED);
         env.add_function("module", module);
-    }))
+        env.add_function("component", crate::component::render_component);
+        env.add_function("css_module", crate::style::css_module);
+        env.add_function("asset_url", crate::asset::asset_url);
+        register_templates(env);
+    })))
 });
 
-fn module(path: String, form: Option<Value>) -> Result<String, minijinja::Error> {
-    let path = path_of_endpoint(path);
-    let path = path.trim_end_matches(template_extension());
+/// The environment as it is now, which stays the same for as long as a render holds onto it.
+pub(crate) fn environment() -> Arc<Environment<'static>> {
+    ENVIRONMENT.read().clone()
+}
+
+/// Modifies the environment for every render that starts afterwards. Renders that are still
+/// running keep their snapshot, which is copied rather than waited on.
+pub(crate) fn modify_environment<T>(f: impl FnOnce(&mut Environment<'static>) -> T) -> T {
+    f(Arc::make_mut(&mut ENVIRONMENT.write()))
+}
+
+/// Compiles every template discovered in the template directory ahead of time.
+#[cfg(not(debug_assertions))]
+fn register_templates(env: &mut Environment<'static>) {
+    for template in TEMPLATES.values() {
+        if let Err(e) = env.add_template_owned(template.name.clone(), template.source.clone()) {
+            error!("Error while compiling {}: {}", template.name, e);
+        }
+    }
+}
+
+/// Templates are loaded from disk on first use, and are invalidated by [reload_template].
+#[cfg(debug_assertions)]
+fn register_templates(env: &mut Environment<'static>) {
+    env.set_loader(load_template);
+}
+
+/// Templates are named by their path relative to the template directory, which is also how
+/// `{% extends %}`, `{% include %}` and `{% import %}` refer to each other.
+#[cfg(debug_assertions)]
+fn load_template(name: &str) -> Result<Option<String>, minijinja::Error> {
+    match std::fs::read_to_string(crate::template_dir().join(name)) {
+        Ok(source) => Ok(Some(source)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("could not read template {name}"),
+        )
+        .with_source(e)),
+    }
+}
+
+/// Lazily loads another endpoint with htmx. Keyword arguments customize how it's loaded:
+///
+/// - `trigger`: the `hx-trigger` that loads the module, `revealed` by default.
+/// - `swap`: the `hx-swap` strategy, `outerHTML` by default.
+/// - `tag`: the element that's swapped out, `div` by default.
+/// - `block`: the block to render, for templates that define it (see [Template::render_block](crate::Template::render_block)).
+/// - `placeholder`: a template rendered inside the element while it's loading.
+/// - `error`: a template that replaces the element if the request fails.
+/// - `inline`: renders the module in place through its component (see [component](crate::component)),
+///   instead of loading it with htmx.
+///
+/// Templates are named relative to the template directory, and are rendered with the module's `form`.
+fn module(
+    state: &minijinja::State,
+    path: String,
+    form: Option<Value>,
+    kwargs: Kwargs,
+) -> Result<Value, minijinja::Error> {
+    let template_name = path_of_endpoint(path);
+    let path = template_name.trim_end_matches(template_extension());
 
-    match (form.as_ref().map(Value::kind), form) {
+    let url = match (form.as_ref().map(Value::kind), &form) {
         (Some(ValueKind::Map), Some(form)) => match serde_urlencoded::to_string(form) {
-            Ok(form) => Ok(format!(
-                r#"<div hx-trigger="revealed" hx-swap="outerHTML" hx-get="{path}?{form}"></div>"#,
-            )),
-            Err(e) => Err(minijinja::Error::new(
-                minijinja::ErrorKind::BadSerialization,
-                format!("invalid form data: {e}"),
-            )),
+            Ok(form) => format!("{path}?{form}"),
+            Err(e) => {
+                return Err(minijinja::Error::new(
+                    minijinja::ErrorKind::BadSerialization,
+                    format!("invalid form data: {e}"),
+                ))
+            }
         },
-        (Some(_), _) => Err(minijinja::Error::new(
-            minijinja::ErrorKind::BadSerialization,
-            "form data should be a map",
-        )),
-        (None, _) => Ok(format!(
-            r#"<div hx-trigger="revealed" hx-swap="outerHTML" hx-get="{path}"></div>"#
-        )),
+        (Some(_), _) => {
+            return Err(minijinja::Error::new(
+                minijinja::ErrorKind::BadSerialization,
+                "form data should be a map",
+            ))
+        }
+        (None, _) => path.to_string(),
+    };
+
+    let trigger: Option<&str> = kwargs.get("trigger")?;
+    let swap: Option<&str> = kwargs.get("swap")?;
+    let tag: Option<&str> = kwargs.get("tag")?;
+    let block: Option<&str> = kwargs.get("block")?;
+    let placeholder: Option<&str> = kwargs.get("placeholder")?;
+    let error: Option<&str> = kwargs.get("error")?;
+    let inline: Option<bool> = kwargs.get("inline")?;
+    kwargs.assert_all_used()?;
+
+    let deferred = trigger.is_some()
+        || swap.is_some()
+        || tag.is_some()
+        || placeholder.is_some()
+        || error.is_some();
+    if inline == Some(true) && deferred {
+        return Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            "inline modules can't have a trigger, swap, tag, placeholder or error",
+        ));
+    }
+    if inline == Some(true) && !crate::component::exists(&template_name) {
+        return Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!(
+                "module {path} can't be inlined, since its endpoint's handler wouldn't run. \
+                 Register a component named {path} to render it in place"
+            ),
+        ));
+    }
+    if is_inline(&template_name, inline, deferred) {
+        return render_inline(state, &template_name, &url, block);
     }
+
+    let tag = tag.unwrap_or("div");
+    if tag.is_empty() || !tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("invalid module tag: {tag}"),
+        ));
+    }
+
+    let render_fragment = |name: &str| {
+        state
+            .env()
+            .get_template(name)?
+            .render(minijinja::context!(form))
+    };
+
+    let mut attributes = format!(
+        r#"hx-trigger="{}" hx-swap="{}" hx-get="{}""#,
+        escape_attribute(trigger.unwrap_or("revealed")),
+        escape_attribute(swap.unwrap_or("outerHTML")),
+        escape_attribute(&url),
+    );
+    if let Some(block) = block {
+        let headers = serde_json::json!({ "Hyro-Block": block }).to_string();
+        attributes += &format!(r#" hx-headers="{}""#, escape_attribute(&headers));
+    }
+    if error.is_some() {
+        let fallback = "this.outerHTML = this.querySelector('template[hyro-error]').innerHTML";
+        attributes +=
+            &format!(r#" hx-on="htmx:responseError: {fallback}&#10;htmx:sendError: {fallback}""#);
+    }
+
+    let mut contents = match placeholder {
+        Some(name) => render_fragment(name)?,
+        None => String::new(),
+    };
+    if let Some(name) = error {
+        contents += &format!("<template hyro-error>{}</template>", render_fragment(name)?);
+    }
+
+    Ok(Value::from_safe_string(format!(
+        "<{tag} {attributes}>{contents}</{tag}>"
+    )))
 }
 
+/// Whether a module is rendered in place, or loaded by htmx once the page has loaded. Only modules
+/// with a component can be rendered in place, and modules that customize how they're loaded are
+/// never inlined, unless asked to.
+pub(crate) fn is_inline(template_name: &str, inline: Option<bool>, deferred: bool) -> bool {
+    crate::component::exists(template_name)
+        && inline.unwrap_or(!deferred && INLINE_MODULES.get().copied().unwrap_or(false))
+}
+
+/// Renders a module's template as part of the page through its component, with the `form` it
+/// would have been requested with from `url`. The form goes through the query string like it
+/// would over HTTP, so its values are all strings.
+fn render_inline(
+    state: &minijinja::State,
+    template_name: &str,
+    url: &str,
+    block: Option<&str>,
+) -> Result<Value, minijinja::Error> {
+    let query = url.split_once('?').map_or("", |(_, query)| query);
+    let form: HashMap<String, String> = serde_urlencoded::from_str(query).unwrap_or_default();
+    let context = crate::component::context_of(template_name, Props::Form(form.clone()))
+        .unwrap_or_else(|| {
+            Err(minijinja::Error::new(
+                minijinja::ErrorKind::InvalidOperation,
+                format!("no component named {template_name}"),
+            ))
+        })?;
+    let context = minijinja::context! {
+        props => form.clone(),
+        form,
+        htmx => Value::from_serializable(&crate::Htmx::default()),
+        ..context
+    };
+
+    let template = state.env().get_template(template_name)?;
+    let rendered = match block {
+        Some(block) => template.eval_to_state(context)?.render_block(block)?,
+        None => template.render(context)?,
+    };
+
+    Ok(Value::from_safe_string(rendered))
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+/// Tags the first element of a rendered template with the endpoint it came from. This is done
+/// after rendering, since the first tag of the source may belong to a layout it extends.
+/// Rendered blocks are tagged with their name too, and routes with parameters with the URL they
+/// were rendered for, so HMR can request the same thing again.
 #[cfg(debug_assertions)]
-fn inject_template_path(path: &str, template: &str) -> String {
-    let loc = if let Some(stripped) = template.strip_prefix("<!DOCTYPE html>") {
-        stripped.find('>').map(|i| i + 15)
+fn inject_template_path(
+    path: &str,
+    url: Option<&str>,
+    block: Option<&str>,
+    template: &str,
+) -> String {
+    let offset = template.len() - template.trim_start().len();
+    let loc = if let Some(stripped) = template[offset..].strip_prefix("<!DOCTYPE html>") {
+        stripped.find('>').map(|i| i + offset + 15)
     } else {
-        template.find('>')
+        template[offset..].find('>').map(|i| i + offset)
     };
 
     if let Some(insert_pos) = loc {
         format!(
-            "{} hmr-path=\"{}\"{}",
+            "{} hmr-path=\"{}\"{}{}{}",
             &template[..insert_pos],
             path,
+            url.map_or(String::new(), |u| format!(
+                " hmr-url=\"{}\"",
+                escape_attribute(u)
+            )),
+            block.map_or(String::new(), |b| format!(" hmr-block=\"{b}\"")),
             &template[insert_pos..]
         )
     } else {
@@ -62,6 +288,31 @@ fn inject_template_path(path: &str, template: &str) -> String {
     }
 }
 
+/// Fills in the parameters of an endpoint's route, e.g. `/users/:id` becomes `/users/3`.
+/// Endpoints without parameters are already a URL.
+#[cfg(debug_assertions)]
+fn url_of_endpoint(endpoint: &str, value: &Value) -> Option<String> {
+    if !endpoint.contains("/:") {
+        return None;
+    }
+
+    let params = value.get_attr("params").ok()?;
+    Some(
+        endpoint
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(param) => params
+                    .get_attr(param)
+                    .ok()
+                    .filter(|value| !value.is_undefined())
+                    .map_or_else(|| segment.to_string(), |value| value.to_string()),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
 #[cfg(debug_assertions)]
 fn inject_hmr(template: &str) -> String {
     if let Some(head_end_pos) = template.find("</head>") {
@@ -76,88 +327,523 @@ fn inject_hmr(template: &str) -> String {
     }
 }
 
-#[cfg(not(debug_assertions))]
-pub(crate) fn render<S: AsRef<str> + std::fmt::Debug>(
-    template_name: S,
-    value: minijinja::value::Value,
-) -> RenderedTemplate {
-    let template = TEMPLATES.get(template_name.as_ref()).unwrap();
+/// Which part of a template to render.
+pub(crate) enum Part {
+    Page,
+    /// A single block, failing when the template doesn't define it.
+    Block(String),
+    /// A single block if the template defines it, and the whole page otherwise.
+    BlockOrPage(String),
+}
 
-    if template.can_skip_rendering {
-        return into_rendered_template(Cow::Borrowed(template.source.as_str()));
-    } else {
-        match ENVIRONMENT.lock().render_str(&template.source, value) {
-            Ok(t) => return into_rendered_template(Cow::Owned(t)),
-            Err(e) => {
-                error!("Error while rendering {}: {:?}", template_name.as_ref(), e);
-                return into_rendered_template(Cow::Borrowed(""));
+/// Renders `part` of the template into `w`, returning the name of the block that was rendered.
+fn render_part<W: std::io::Write>(
+    template: &minijinja::Template,
+    value: Value,
+    part: Part,
+    mut w: W,
+) -> Result<Option<String>, minijinja::Error> {
+    match part {
+        Part::Page => template.render_to_write(value, w).map(|_| None),
+        Part::Block(block) => template
+            .eval_to_state(value)?
+            .render_block_to_write(&block, w)
+            .map(|_| Some(block)),
+        // Missing blocks are reported before anything is written.
+        Part::BlockOrPage(block) => {
+            match template
+                .eval_to_state(&value)?
+                .render_block_to_write(&block, &mut w)
+            {
+                Err(e) if e.kind() == minijinja::ErrorKind::UnknownBlock => {
+                    template.render_to_write(value, w).map(|_| None)
+                }
+                rendered => rendered.map(|_| Some(block)),
             }
         }
     }
 }
 
-#[cfg(debug_assertions)]
-pub(crate) fn render<S: AsRef<str> + std::fmt::Debug>(
-    template: S,
-    value: minijinja::value::Value,
-) -> RenderedTemplate {
-    init_template(template.as_ref());
+/// Rendered output is streamed in chunks of roughly this size.
+const STREAM_CHUNK_SIZE: usize = 8 * 1024;
 
-    let template_sources = TEMPLATES.sources.lock();
-    let template_source = template_sources.get(template.as_ref()).unwrap().lock();
+/// Collects rendered output into chunks, sending each one to the response body once it's full.
+struct ChunkWriter {
+    tx: MpscSender<String>,
+    /// Raw output, since a write may end partway through a character.
+    buffer: Vec<u8>,
+    /// Debug builds tag the output for HMR once the `<head>` has been rendered, so the start
+    /// of the output is held back until then. Holds the endpoint, its URL and the rendered block.
+    #[cfg(debug_assertions)]
+    pending_injection: Option<(String, Option<String>, Option<String>)>,
+}
 
-    let maybe_rendered = ENVIRONMENT.lock().render_str(
-        &inject_hmr(&inject_template_path(template.as_ref(), &template_source)),
-        value,
-    );
+impl ChunkWriter {
+    fn rendering_block(&mut self, _block: Option<&str>) {
+        #[cfg(debug_assertions)]
+        if let Some((_, _, block)) = &mut self.pending_injection {
+            *block = _block.map(String::from);
+        }
+    }
 
-    match maybe_rendered {
-        Ok(t) => into_rendered_template(Cow::Owned(t)),
-        Err(e) => {
-            error!("Error while rendering {}: {}", template.as_ref(), e);
-            into_rendered_template(Cow::Borrowed(""))
+    #[cfg(not(debug_assertions))]
+    fn send(&mut self, done: bool) -> std::io::Result<()> {
+        let chunk = self.take_chunk(done);
+        self.send_chunk(chunk)
+    }
+
+    #[cfg(debug_assertions)]
+    fn send(&mut self, done: bool) -> std::io::Result<()> {
+        if self.pending_injection.is_some()
+            && !done
+            && !self.buffer.windows(7).any(|window| window == b"</head>")
+            && self.buffer.len() < 8 * STREAM_CHUNK_SIZE
+        {
+            return Ok(());
+        }
+
+        let chunk = self.take_chunk(done);
+        let chunk = match self.pending_injection.take() {
+            Some((path, url, block)) => inject_hmr(&inject_template_path(
+                &path,
+                url.as_deref(),
+                block.as_deref(),
+                &chunk,
+            )),
+            None => chunk,
+        };
+        self.send_chunk(chunk)
+    }
+
+    /// Takes the buffered output, up to the last complete character. A character that's split
+    /// between writes stays in the buffer until the rest of it is written.
+    fn take_chunk(&mut self, done: bool) -> String {
+        let complete = match std::str::from_utf8(&self.buffer) {
+            Err(e) if e.error_len().is_none() && !done => e.valid_up_to(),
+            _ => self.buffer.len(),
+        };
+        let rest = self.buffer.split_off(complete);
+        let chunk = std::mem::replace(&mut self.buffer, rest);
+
+        String::from_utf8(chunk)
+            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+    }
+
+    fn send_chunk(&mut self, chunk: String) -> std::io::Result<()> {
+        if chunk.is_empty() || mpsc_send_blocking(&mut self.tx, chunk) {
+            Ok(())
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "the response was dropped",
+            ))
+        }
+    }
+}
+
+impl std::io::Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= STREAM_CHUNK_SIZE {
+            self.send(false)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send(false)
+    }
+}
+
+/// Renders on the runtime's blocking pool, so that chunks can be sent while the rest of the
+/// template is still rendering. A slow client only holds up its own snapshot of the environment.
+fn spawn_stream(name: String, endpoint: String, value: Value, part: Part) -> MpscReceiver<String> {
+    let (tx, rx) = mpsc_channel(16);
+
+    crate::runtime::spawn_blocking(move || {
+        let mut writer = ChunkWriter {
+            tx,
+            buffer: Vec::new(),
+            #[cfg(debug_assertions)]
+            pending_injection: Some((endpoint.clone(), url_of_endpoint(&endpoint, &value), None)),
+        };
+
+        let env = environment();
+        let rendered = env.get_template(&name).and_then(|t| match part {
+            // The block is tried first so that HMR knows what's being rendered before any
+            // output is sent. Missing blocks are reported before anything is written.
+            Part::BlockOrPage(block) => {
+                writer.rendering_block(Some(&block));
+                match render_part(&t, value.clone(), Part::Block(block), &mut writer) {
+                    Err(e) if e.kind() == minijinja::ErrorKind::UnknownBlock => {
+                        writer.rendering_block(None);
+                        render_part(&t, value, Part::Page, &mut writer)
+                    }
+                    rendered => rendered,
+                }
+            }
+            Part::Block(block) => {
+                writer.rendering_block(Some(&block));
+                render_part(&t, value, Part::Block(block), &mut writer)
+            }
+            Part::Page => render_part(&t, value, Part::Page, &mut writer),
+        });
+
+        // The response has already started, so errors can only be logged (and shown at the
+        // end of the page in debug builds).
+        if let Err(e) = rendered {
+            error!("Error while rendering {}: {}", endpoint, e);
+            if cfg!(debug_assertions) {
+                writer.buffer.extend_from_slice(e.to_string().as_bytes());
+            }
         }
+
+        writer.send(true).ok();
+    });
+
+    rx
+}
+
+pub(crate) fn render<S: AsRef<str> + std::fmt::Debug>(
+    template_name: S,
+    value: minijinja::value::Value,
+    part: Part,
+) -> Result<RenderedTemplate, Error> {
+    render_as(template_name.as_ref(), template_name.as_ref(), value, part)
+}
+
+/// Renders a template on behalf of an endpoint, which may be rendering a template other than
+/// its own. Debug builds tag the output with the endpoint, so HMR requests it from there again.
+#[cfg(not(debug_assertions))]
+pub(crate) fn render_as(
+    _endpoint: &str,
+    template_name: &str,
+    value: minijinja::value::Value,
+    part: Part,
+) -> Result<RenderedTemplate, Error> {
+    let Some(template) = TEMPLATES.get(&path_of_endpoint(template_name)) else {
+        let e = Error::MissingTemplate(template_name.to_string());
+        error!("Error while rendering {}: {}", template_name, e);
+        return Err(e);
+    };
+
+    // Templates without any tags can't define blocks either.
+    if template.can_skip_rendering && !matches!(part, Part::Block(_)) {
+        return Ok(into_rendered_template(Cow::Borrowed(
+            template.source.as_str(),
+        )));
     }
+
+    let env = environment();
+
+    // Templates that failed to compile at startup were never registered,
+    // so they're compiled again here to get the error back.
+    env.get_template(&template.name)
+        .or_else(|_| env.template_from_named_str(&template.name, &template.source))
+        .and_then(|t| {
+            let mut rendered = Vec::new();
+            render_part(&t, value, part, &mut rendered)?;
+            Ok(String::from_utf8_lossy(&rendered).into_owned())
+        })
+        .map(|t| into_rendered_template(Cow::Owned(t)))
+        .map_err(Error::from)
+        .tap_err(|e| error!("Error while rendering {}: {}", template_name, e))
 }
 
 #[cfg(debug_assertions)]
-fn init_template(template_name: &str) {
-    let template_exists = TEMPLATES.sources.lock().contains_key(template_name);
-    if !template_exists {
-        reload_template(
-            &crate::template_dir()
-                .join(std::path::Path::new(template_name.trim_start_matches('/')))
-                .display()
-                .to_string(),
+pub(crate) fn render_as(
+    endpoint: &str,
+    template: &str,
+    value: minijinja::value::Value,
+    part: Part,
+) -> Result<RenderedTemplate, Error> {
+    let env = environment();
+    let name = path_of_endpoint(template);
+    let url = url_of_endpoint(endpoint, &value);
+
+    if name != path_of_endpoint(endpoint) {
+        RENDERED_AS
+            .lock()
+            .entry(name.clone())
+            .or_default()
+            .insert(endpoint.to_string());
+    }
+
+    env.get_template(&name)
+        .map_err(|e| match e.kind() {
+            minijinja::ErrorKind::TemplateNotFound => Error::MissingTemplate(template.to_string()),
+            _ => e.into(),
+        })
+        .and_then(|t| {
+            let mut rendered = Vec::new();
+            let block = render_part(&t, value, part, &mut rendered)?;
+            Ok((String::from_utf8_lossy(&rendered).into_owned(), block))
+        })
+        .map(|(t, block)| {
+            into_rendered_template(Cow::Owned(inject_hmr(&inject_template_path(
+                endpoint,
+                url.as_deref(),
+                block.as_deref(),
+                &t,
+            ))))
+        })
+        .tap_err(|e| error!("Error while rendering {}: {}", template, e))
+}
+
+/// Renders a template on its own, without anything added for HMR.
+pub(crate) fn render_standalone(template: &str, value: Value) -> Result<String, Error> {
+    environment()
+        .get_template(&path_of_endpoint(template))
+        .and_then(|t| t.render(value))
+        .map_err(|e| match e.kind() {
+            minijinja::ErrorKind::TemplateNotFound => Error::MissingTemplate(template.to_string()),
+            _ => e.into(),
+        })
+        .tap_err(|e| error!("Error while rendering {}: {}", template, e))
+}
+
+/// Wraps a fragment so that htmx swaps it into the element with the id `target`, wherever the
+/// fragment ends up in a response.
+pub(crate) fn out_of_band(target: &str, html: &str) -> String {
+    format!(
+        r#"<div id="{}" hx-swap-oob="innerHTML">{html}</div>"#,
+        minijinja::HtmlEscape(target)
+    )
+}
+
+#[cfg(not(debug_assertions))]
+pub(crate) fn render_stream<S: AsRef<str> + std::fmt::Debug>(
+    template_name: S,
+    value: minijinja::value::Value,
+    part: Part,
+) -> Result<MpscReceiver<String>, Error> {
+    let Some(template) = TEMPLATES.get(&path_of_endpoint(template_name.as_ref())) else {
+        let e = Error::MissingTemplate(template_name.as_ref().to_string());
+        error!("Error while rendering {}: {}", template_name.as_ref(), e);
+        return Err(e);
+    };
+
+    if template.can_skip_rendering && !matches!(part, Part::Block(_)) {
+        let (mut tx, rx) = mpsc_channel(1);
+        crate::runtime::mpsc_try_send(&mut tx, template.source.clone());
+        return Ok(rx);
+    }
+
+    // Compile errors are returned before the response starts. Templates that failed to compile
+    // at startup were never registered, so they're compiled again here to get the error back.
+    let env = environment();
+    if let Err(e) = env.get_template(&template.name) {
+        let e = Error::from(
+            env.template_from_named_str(&template.name, &template.source)
+                .err()
+                .unwrap_or(e),
         );
+        error!("Error while rendering {}: {}", template_name.as_ref(), e);
+        return Err(e);
     }
+    drop(env);
+
+    Ok(spawn_stream(
+        template.name.clone(),
+        template_name.as_ref().to_string(),
+        value,
+        part,
+    ))
+}
+
+#[cfg(debug_assertions)]
+pub(crate) fn render_stream<S: AsRef<str> + std::fmt::Debug>(
+    template: S,
+    value: minijinja::value::Value,
+    part: Part,
+) -> Result<MpscReceiver<String>, Error> {
+    let name = path_of_endpoint(template.as_ref());
+
+    // Compile errors are returned before the response starts.
+    environment()
+        .get_template(&name)
+        .map_err(|e| match e.kind() {
+            minijinja::ErrorKind::TemplateNotFound => {
+                Error::MissingTemplate(template.as_ref().to_string())
+            }
+            _ => e.into(),
+        })
+        .tap_err(|e| error!("Error while rendering {}: {}", template.as_ref(), e))?;
+
+    Ok(spawn_stream(
+        name,
+        template.as_ref().to_string(),
+        value,
+        part,
+    ))
 }
 
+/// Drops the compiled copy of a template (named relative to the template directory),
+/// so that the loader picks up the new source on the next render. If the new source
+/// doesn't parse, the last working version is kept around instead.
 #[cfg(debug_assertions)]
-pub(crate) fn reload_template(template_name: &str) {
-    let _path = &path_of_endpoint(template_name);
-    let path = std::path::Path::new(_path);
-
-    let template_source = std::fs::read_to_string(path).unwrap();
-
-    match minijinja::machinery::parse(
-        &template_source,
-        &std::env::current_dir()
-            .unwrap()
-            .join(path)
-            .display()
-            .to_string(),
-    ) {
+pub(crate) fn reload_template(name: &str) -> Result<(), crate::overlay::CompileError> {
+    let path = crate::template_dir().join(name);
+
+    let template_source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(e) => {
+            error!("Error while reading {}: {}", path.display(), e);
+            return Ok(());
+        }
+    };
+
+    match minijinja::machinery::parse(&template_source, name) {
         Ok(_) => {
-            TEMPLATES.sources.lock().insert(
-                crate::endpointof(_path.trim_start_matches("templates"))
-                    .unwrap()
-                    .into(),
-                Mutex::new(template_source),
-            );
+            modify_environment(|env| env.remove_template(name));
+            Ok(())
         }
         Err(e) => {
             error!("{e}");
+            Err(crate::overlay::CompileError::template(
+                name,
+                &template_source,
+                &e,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::StreamExt;
+    #[cfg(debug_assertions)]
+    use minijinja::context;
+
+    #[test]
+    fn chunks_end_between_characters() {
+        let (tx, mut rx) = mpsc_channel(16);
+        let mut writer = ChunkWriter {
+            tx,
+            buffer: Vec::new(),
+            #[cfg(debug_assertions)]
+            pending_injection: None,
+        };
+
+        // Written a byte at a time, chunks fill up partway through an `ö`.
+        let text = "aö".repeat(STREAM_CHUNK_SIZE);
+        for byte in text.as_bytes() {
+            std::io::Write::write_all(&mut writer, &[*byte]).unwrap();
+        }
+        writer.send(true).unwrap();
+        drop(writer);
+
+        let mut streamed = String::new();
+        let mut chunks = 0;
+        while let Some(chunk) = crate::runtime::block_on(rx.next()) {
+            assert!(!chunk.contains(char::REPLACEMENT_CHARACTER));
+            streamed += &chunk;
+            chunks += 1;
+        }
+        assert!(chunks > 1);
+        assert_eq!(streamed, text);
+    }
+
+    fn render_str(source: &str) -> Result<String, minijinja::Error> {
+        crate::tests::use_test_templates();
+        environment().render_str(source, ())
+    }
+
+    #[test]
+    fn modules_are_loaded_by_htmx() {
+        let html = render_str(r#"{{ module("stats") }}"#).unwrap();
+        assert_eq!(
+            html,
+            r#"<div hx-trigger="revealed" hx-swap="outerHTML" hx-get="stats"></div>"#
+        );
+
+        let html = render_str(r#"{{ module("stats", block="numbers") }}"#).unwrap();
+        assert!(
+            html.contains(r#" hx-headers="{&quot;Hyro-Block&quot;:&quot;numbers&quot;}""#),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn module_kwargs_customize_the_element() {
+        let html = render_str(
+            r#"{{ module("stats", {"range": "week"},
+                trigger="every 5s",
+                swap="innerHTML",
+                tag="section",
+                placeholder="_skeleton.html.jinja2",
+                error="_stats-error.html.jinja2") }}"#,
+        )
+        .unwrap();
+
+        assert!(
+            html.starts_with(
+                r#"<section hx-trigger="every 5s" hx-swap="innerHTML" hx-get="stats?range=week" hx-on="#
+            ),
+            "{html}"
+        );
+        assert!(
+            html.ends_with(concat!(
+                "><p>Loading week stats</p>",
+                "<template hyro-error><p>Stats are unavailable</p></template></section>"
+            )),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn module_kwargs_are_checked() {
+        assert!(render_str(r#"{{ module("stats", tag="a onclick") }}"#).is_err());
+        assert!(render_str(r#"{{ module("stats", swapp="innerHTML") }}"#).is_err());
+        assert!(render_str(r#"{{ module("stats", inline=true, trigger="load") }}"#).is_err());
+    }
+
+    #[test]
+    fn modules_are_inlined_through_their_component() {
+        #[derive(serde::Deserialize)]
+        struct Profile {
+            age: u8,
         }
+        crate::component(
+            "profile",
+            |props: Profile| minijinja::context!(name => "Ferris", age => props.age + 1),
+        );
+
+        let html = render_str(r#"{{ module("profile", {"age": 7}, inline=true) }}"#).unwrap();
+        assert_eq!(html, "<p>Ferris is 8, asked for 7</p>");
+
+        let e = render_str(r#"{{ module("stats", inline=true) }}"#).unwrap_err();
+        assert!(
+            e.to_string().contains("Register a component named stats"),
+            "{e}"
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn templates_compile_once_until_reloaded() {
+        crate::tests::use_test_templates();
+        let name = "_reloaded.html.jinja2";
+        let path = crate::template_dir().join(name);
+        let render =
+            |env: &Environment| env.get_template(name).unwrap().render(context!()).unwrap();
+
+        std::fs::write(&path, "first").unwrap();
+        let before = environment();
+        assert_eq!(render(&before), "first");
+
+        std::fs::write(&path, "second").unwrap();
+        assert_eq!(render(&environment()), "first");
+        assert!(Arc::ptr_eq(&before, &environment()));
+
+        reload_template(name).unwrap();
+        let after = environment();
+        let (old, new) = (render(&before), render(&after));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!Arc::ptr_eq(&before, &after));
+        assert_eq!(old, "first");
+        assert_eq!(new, "second");
     }
 }
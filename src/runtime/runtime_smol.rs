@@ -1,11 +1,12 @@
+pub use async_broadcast::Receiver as BroadcastReceiver;
+pub use async_broadcast::Sender as BroadcastSender;
 pub use async_channel::bounded as mpsc_channel;
-pub use async_channel::bounded as broadcast_channel;
 pub use async_channel::Receiver as MpscReceiver;
-pub use async_channel::Receiver as BroadcastReceiver;
 pub use async_channel::Sender as MpscSender;
-pub use async_channel::Sender as BroadcastSender;
+#[cfg(any(debug_assertions, test))]
 pub use async_io::block_on;
-pub use futures_lite::StreamExt;
+pub use futures_lite::stream::unfold as stream_unfold;
+pub use futures_lite::{AsyncRead, Stream, StreamExt};
 
 #[cfg(debug_assertions)]
 pub fn spawn<F: futures_lite::Future + Send + 'static>(future: F)
@@ -15,14 +16,77 @@ where
     async_global_executor::spawn(future).detach();
 }
 
-#[cfg(debug_assertions)]
-pub fn broadcast_subscribe<T>(
+/// Like Tokio's broadcast channels, subscribers that fall behind miss the oldest messages,
+/// instead of holding up the sender.
+pub fn broadcast_channel<T>(capacity: usize) -> (BroadcastSender<T>, BroadcastReceiver<T>) {
+    let (mut sender, receiver) = async_broadcast::broadcast(capacity);
+    sender.set_overflow(true);
+    (sender, receiver)
+}
+
+pub fn broadcast_subscribe<T: Clone>(
     broadcast: &(BroadcastSender<T>, BroadcastReceiver<T>),
-) -> &BroadcastReceiver<T> {
-    &broadcast.1
+) -> BroadcastReceiver<T> {
+    broadcast.1.new_receiver()
+}
+
+/// Sends to every subscriber. Never waits, since the oldest message is dropped when one falls behind.
+pub fn broadcast_send<T: Clone>(sender: &BroadcastSender<T>, value: T) {
+    sender.try_broadcast(value).ok();
+}
+
+/// The next message, skipping any that were missed by falling behind.
+/// Returns `None` once the channel is closed.
+#[cfg(debug_assertions)]
+pub async fn broadcast_recv<T: Clone>(receiver: &mut BroadcastReceiver<T>) -> Option<T> {
+    use async_broadcast::RecvError;
+
+    loop {
+        match receiver.recv().await {
+            Ok(value) => return Some(value),
+            Err(RecvError::Overflowed(_)) => continue,
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Every message sent after subscribing, skipping any that were missed by falling behind.
+pub fn broadcast_stream<T: Clone + Send + Sync + 'static>(
+    receiver: BroadcastReceiver<T>,
+) -> impl Stream<Item = T> + Send + Sync {
+    receiver
 }
 
 #[cfg(debug_assertions)]
 pub fn instant_now() -> std::time::Instant {
     std::time::Instant::now()
 }
+
+/// Sends without waiting, returning `false` if the channel is full or closed.
+pub fn mpsc_try_send<T>(sender: &mut MpscSender<T>, value: T) -> bool {
+    sender.try_send(value).is_ok()
+}
+
+/// Waits for whichever future finishes first, dropping the other.
+pub async fn race<T>(
+    a: impl std::future::Future<Output = T>,
+    b: impl std::future::Future<Output = T>,
+) -> T {
+    futures_lite::future::or(a, b).await
+}
+
+/// Sends from outside of the runtime, waiting while the channel is full.
+/// Returns `false` once the receiver has been dropped.
+pub fn mpsc_send_blocking<T>(sender: &mut MpscSender<T>, value: T) -> bool {
+    sender.send_blocking(value).is_ok()
+}
+
+/// Runs blocking work on the bounded pool of blocking threads shared with the executor.
+pub fn spawn_blocking<F: FnOnce() + Send + 'static>(f: F) {
+    async_global_executor::spawn_blocking(f).detach();
+}
+
+/// Waits until `duration` has passed.
+pub async fn sleep(duration: std::time::Duration) {
+    async_io::Timer::after(duration).await;
+}
@@ -2,6 +2,10 @@ pub use futures::channel::mpsc::channel as mpsc_channel;
 pub use futures::channel::mpsc::Receiver as MpscReceiver;
 pub use futures::channel::mpsc::Sender as MpscSender;
 pub use futures::executor::block_on;
+#[cfg(feature = "framework-trillium")]
+pub use futures::stream::unfold as stream_unfold;
+#[cfg(feature = "framework-trillium")]
+pub use futures::{io::AsyncRead, Stream};
 pub use futures::{SinkExt, StreamExt};
 
 pub use tokio::spawn;
@@ -19,3 +23,67 @@ pub fn broadcast_subscribe<T>(
 ) -> BroadcastReceiver<T> {
     broadcast.0.subscribe()
 }
+
+/// Sends to every subscriber. Never waits, since the oldest message is dropped when one falls behind.
+pub fn broadcast_send<T>(sender: &BroadcastSender<T>, value: T) {
+    sender.send(value).ok();
+}
+
+/// The next message, skipping any that were missed by falling behind.
+/// Returns `None` once the channel is closed.
+pub async fn broadcast_recv<T: Clone>(receiver: &mut BroadcastReceiver<T>) -> Option<T> {
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        match receiver.recv().await {
+            Ok(value) => return Some(value),
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Every message sent after subscribing, skipping any that were missed by falling behind.
+pub fn broadcast_stream<T: Clone + Send + 'static>(
+    receiver: BroadcastReceiver<T>,
+) -> impl futures::Stream<Item = T> + Send {
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        let value = broadcast_recv(&mut receiver).await?;
+        Some((value, receiver))
+    })
+}
+
+/// Sends without waiting, returning `false` if the channel is full or closed.
+pub fn mpsc_try_send<T>(sender: &mut MpscSender<T>, value: T) -> bool {
+    sender.try_send(value).is_ok()
+}
+
+/// Waits for whichever future finishes first, dropping the other.
+pub async fn race<T>(
+    a: impl std::future::Future<Output = T>,
+    b: impl std::future::Future<Output = T>,
+) -> T {
+    futures::pin_mut!(a, b);
+    futures::future::select(a, b).await.factor_first().0
+}
+
+/// Sends from outside of the runtime, waiting while the channel is full.
+/// Returns `false` once the receiver has been dropped.
+pub fn mpsc_send_blocking<T>(sender: &mut MpscSender<T>, value: T) -> bool {
+    block_on(sender.send(value)).is_ok()
+}
+
+/// Runs blocking work on the runtime's bounded pool of blocking threads. Outside of a runtime,
+/// the work gets a thread of its own.
+pub fn spawn_blocking<F: FnOnce() + Send + 'static>(f: F) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => drop(handle.spawn_blocking(f)),
+        Err(_) => drop(std::thread::spawn(f)),
+    }
+}
+
+/// Waits until `duration` has passed.
+#[cfg(feature = "framework-trillium")]
+pub async fn sleep(duration: std::time::Duration) {
+    tokio::time::sleep(duration).await;
+}
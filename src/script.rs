@@ -0,0 +1,590 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use oxc::allocator::Allocator;
+use oxc::ast::ast::{
+    Declaration, ExportDefaultDeclarationKind, ImportDeclarationSpecifier, Statement,
+};
+use oxc::ast::AstKind;
+use oxc::codegen::{Codegen, CodegenOptions};
+use oxc::diagnostics::OxcDiagnostic;
+use oxc::parser::Parser;
+use oxc::semantic::SemanticBuilder;
+use oxc::span::{GetSpan, SourceType, Span};
+use oxc::transformer::{TransformOptions, Transformer};
+use oxc_sourcemap::SourceMap;
+use parking_lot::RwLock;
+
+/// Every script served with `with_bundled_js`, and the endpoint it's served at.
+pub(crate) static SCRIPTS: Lazy<RwLock<Vec<(String, PathBuf)>>> = Lazy::new(Default::default);
+
+/// Extensions tried, in order, for imports that leave them out.
+pub(crate) const EXTENSIONS: [&str; 5] = ["ts", "tsx", "js", "mjs", "jsx"];
+
+/// Modules are functions of their `require` and `exports`, which are run the first time they're
+/// required. Exports are getters, so that they're live bindings like they would be in an ES module.
+const RUNTIME: &str = r#"(() => {
+"use strict";
+const __hyro_modules = [], __hyro_cache = [];
+function __hyro_define(id, module) { __hyro_modules[id] = module; }
+function __hyro_require(id) {
+  if (!__hyro_cache[id]) { __hyro_cache[id] = {}; __hyro_modules[id](__hyro_require, __hyro_cache[id]); }
+  return __hyro_cache[id];
+}
+function __hyro_export(exports, getters) {
+  for (const name in getters) Object.defineProperty(exports, name, { enumerable: true, get: getters[name] });
+}
+function __hyro_export_star(exports, module) {
+  for (const name in module) if (name !== "default" && !(name in exports)) __hyro_export(exports, { [name]: () => module[name] });
+}
+"#;
+
+/// Registers a script, returning an error if the endpoint is already taken.
+pub(crate) fn add_script(endpoint: &str, entry: &Path) -> Result<(), String> {
+    let mut scripts = SCRIPTS.write();
+    if scripts.iter().any(|(e, _)| e == endpoint) {
+        return Err(format!("A script is already served at {endpoint}"));
+    }
+
+    scripts.push((endpoint.to_string(), entry.to_path_buf()));
+    Ok(())
+}
+
+#[derive(Debug)]
+pub(crate) struct BundleJSError {
+    pub path: PathBuf,
+    /// Where the error is in the file, as a byte offset, when it's known. Only the error
+    /// overlay shows it.
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    pub offset: Option<usize>,
+    pub message: String,
+}
+
+impl BundleJSError {
+    fn new(path: &Path, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            offset: None,
+            message: message.into(),
+        }
+    }
+
+    fn diagnostic(path: &Path, diagnostic: &OxcDiagnostic) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            offset: diagnostic
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.first())
+                .map(|label| label.offset()),
+            message: diagnostic.message.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for BundleJSError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+/// A module once its types are stripped, and its imports and exports are linked to other modules.
+struct Module {
+    /// Defines the module's exports and requires its imports, before any of its own code runs,
+    /// like an ES module's imports are hoisted. Kept on a line of its own, ahead of `code`.
+    prelude: String,
+    code: String,
+    map: Option<SourceMap>,
+}
+
+/// Bundles an ES module with everything it imports into a single script. TypeScript types are
+/// stripped. Debug builds inline a source map, and release builds are minified.
+pub(crate) fn bundle_js(entry: &Path) -> Result<String, BundleJSError> {
+    let (code, map) = bundle(entry)?;
+
+    #[cfg(debug_assertions)]
+    {
+        let url = map.to_data_url();
+        Ok(format!("{code}//# sourceMappingURL={url}\n"))
+    }
+
+    #[cfg(not(debug_assertions))]
+    {
+        drop(map);
+        minify(entry, &code)
+    }
+}
+
+/// Defines every module the entrypoint imports, and requires the entrypoint, along with a source
+/// map of the modules. Only debug builds map their sources, so release builds' map is empty.
+fn bundle(entry: &Path) -> Result<(String, SourceMap), BundleJSError> {
+    let mut modules = Vec::new();
+    load(
+        &crate::embedded::normalize(entry),
+        &mut modules,
+        &mut HashMap::new(),
+    )?;
+
+    let mut code = String::from(RUNTIME);
+    let mut maps = Vec::new();
+    for (id, module) in modules.into_iter().enumerate() {
+        let Some(module) = module else { continue };
+
+        code += &format!("__hyro_define({id}, function (__hyro_require, __hyro_exports) {{\n");
+        code += &module.prelude;
+        code += "\n";
+        if let Some(map) = module.map {
+            maps.push((map, code.matches('\n').count() as u32));
+        }
+        code += &module.code;
+        code += "\n});\n";
+    }
+    code += "__hyro_require(0);\n})();\n";
+
+    let maps: Vec<_> = maps.iter().map(|(map, line)| (map, *line)).collect();
+    let map = oxc_sourcemap::ConcatSourceMapBuilder::from_sourcemaps(&maps).into_sourcemap();
+    Ok((code, map))
+}
+
+/// Loads a module and everything it imports, returning its id. The entrypoint's id is 0.
+fn load(
+    path: &Path,
+    modules: &mut Vec<Option<Module>>,
+    ids: &mut HashMap<PathBuf, usize>,
+) -> Result<usize, BundleJSError> {
+    if let Some(id) = ids.get(path) {
+        return Ok(*id);
+    }
+
+    // Registered before its imports are loaded, so that circular imports resolve to it.
+    let id = modules.len();
+    ids.insert(path.to_path_buf(), id);
+    modules.push(None);
+
+    let source = match crate::embedded::get(path) {
+        Some(source) => source.to_string(),
+        None => {
+            std::fs::read_to_string(path).map_err(|e| BundleJSError::new(path, e.to_string()))?
+        }
+    };
+
+    let (code, map) = strip_types(path, &source)?;
+    let (prelude, code) = link(path, &code, |specifier| {
+        let resolved = resolve(specifier, path).ok_or_else(|| {
+            BundleJSError::new(
+                path,
+                format!("Can't resolve \"{specifier}\". Only relative imports can be bundled"),
+            )
+        })?;
+        load(&resolved, modules, ids)
+    })?;
+
+    modules[id] = Some(Module { prelude, code, map });
+    Ok(id)
+}
+
+/// Compiles TypeScript and JSX down to JavaScript, along with a source map in debug builds.
+fn strip_types(path: &Path, source: &str) -> Result<(String, Option<SourceMap>), BundleJSError> {
+    let allocator = Allocator::default();
+    let source_type = SourceType::from_path(path)
+        .map_err(|e| BundleJSError::new(path, e.to_string()))?
+        .with_module(true);
+
+    let parsed = Parser::new(&allocator, source, source_type).parse();
+    if let Some(error) = parsed.errors.first() {
+        return Err(BundleJSError::diagnostic(path, error));
+    }
+
+    let mut program = parsed.program;
+    let scoping = SemanticBuilder::new()
+        .build(&program)
+        .semantic
+        .into_scoping();
+    let transformed = Transformer::new(&allocator, path, &TransformOptions::default())
+        .build_with_scoping(scoping, &mut program);
+    if let Some(error) = transformed.errors.first() {
+        return Err(BundleJSError::diagnostic(path, error));
+    }
+
+    let printed = Codegen::new()
+        .with_options(CodegenOptions {
+            source_map_path: cfg!(debug_assertions).then(|| path.to_path_buf()),
+            ..Default::default()
+        })
+        .build(&program);
+
+    Ok((printed.code, printed.map))
+}
+
+/// Rewrites a module's imports into `require`s, and its exports into getters, returning them as
+/// the module's prelude along with the rest of its code. Lines are kept where they are, so that
+/// the module's source map still lines up.
+fn link(
+    path: &Path,
+    code: &str,
+    mut require: impl FnMut(&str) -> Result<usize, BundleJSError>,
+) -> Result<(String, String), BundleJSError> {
+    let allocator = Allocator::default();
+    let parsed = Parser::new(&allocator, code, SourceType::mjs()).parse();
+    if let Some(error) = parsed.errors.first() {
+        // The error is in the code with its types stripped, so its offset doesn't point into the
+        // file on disk.
+        return Err(BundleJSError::new(path, error.message.to_string()));
+    }
+
+    let program = parsed.program;
+    let semantic = SemanticBuilder::new().build(&program).semantic;
+
+    let mut edits: Vec<(Span, String)> = Vec::new();
+    // Imported modules are required in the order they're imported, before the module runs.
+    let mut requires: Vec<String> = Vec::new();
+    let mut exports: Vec<(String, String)> = Vec::new();
+    // Imported bindings, and what they're replaced with wherever they're referenced.
+    let mut imports = Vec::new();
+
+    for (i, statement) in program.body.iter().enumerate() {
+        let module = format!("__hyro_{i}");
+
+        match statement {
+            Statement::ImportDeclaration(import) => {
+                let id = require(&import.source.value)?;
+                requires.push(format!("const {module} = __hyro_require({id});"));
+                edits.push((import.span, blank(code, import.span)));
+
+                for specifier in import.specifiers.iter().flatten() {
+                    let (local, replacement) = match specifier {
+                        ImportDeclarationSpecifier::ImportSpecifier(s) => {
+                            (&s.local, member(&module, &s.imported.name()))
+                        }
+                        ImportDeclarationSpecifier::ImportDefaultSpecifier(s) => {
+                            (&s.local, member(&module, "default"))
+                        }
+                        ImportDeclarationSpecifier::ImportNamespaceSpecifier(s) => {
+                            (&s.local, module.clone())
+                        }
+                    };
+                    imports.push((local.symbol_id(), local.name.to_string(), replacement));
+                }
+            }
+            Statement::ExportNamedDeclaration(export) => {
+                match (&export.declaration, &export.source) {
+                    (Some(declaration), _) => {
+                        let names: Vec<String> = match declaration {
+                            Declaration::VariableDeclaration(variables) => variables
+                                .declarations
+                                .iter()
+                                .flat_map(|d| d.id.get_binding_identifiers())
+                                .map(|ident| ident.name.to_string())
+                                .collect(),
+                            declaration => declaration
+                                .id()
+                                .map(|ident| ident.name.to_string())
+                                .into_iter()
+                                .collect(),
+                        };
+
+                        exports.extend(names.into_iter().map(|name| (name.clone(), name)));
+                        edits.push((
+                            Span::new(export.span.start, declaration.span().start),
+                            String::new(),
+                        ));
+                    }
+                    (None, Some(source)) => {
+                        let id = require(&source.value)?;
+                        for specifier in &export.specifiers {
+                            let exported = specifier.exported.name().to_string();
+                            exports.push((exported, member(&module, &specifier.local.name())));
+                        }
+                        requires.push(format!("const {module} = __hyro_require({id});"));
+                        edits.push((export.span, blank(code, export.span)));
+                    }
+                    (None, None) => {
+                        for specifier in &export.specifiers {
+                            let exported = specifier.exported.name().to_string();
+                            exports.push((exported, specifier.local.name().to_string()));
+                        }
+                        edits.push((export.span, blank(code, export.span)));
+                    }
+                }
+            }
+            Statement::ExportDefaultDeclaration(export) => {
+                let named = match &export.declaration {
+                    ExportDefaultDeclarationKind::FunctionDeclaration(f) => f.id.as_ref(),
+                    ExportDefaultDeclarationKind::ClassDeclaration(c) => c.id.as_ref(),
+                    _ => None,
+                };
+                let start = Span::new(export.span.start, export.declaration.span().start);
+
+                match named {
+                    Some(ident) => {
+                        exports.push(("default".into(), ident.name.to_string()));
+                        edits.push((start, String::new()));
+                    }
+                    None => {
+                        exports.push(("default".into(), "__hyro_default".into()));
+                        edits.push((start, "const __hyro_default = ".into()));
+                        // Anonymous functions and classes become expressions, which need ending.
+                        edits.push((Span::empty(export.span.end), ";".into()));
+                    }
+                }
+            }
+            Statement::ExportAllDeclaration(export) => {
+                let id = require(&export.source.value)?;
+                match &export.exported {
+                    Some(exported) => {
+                        exports.push((exported.name().to_string(), module.clone()));
+                        requires.push(format!("const {module} = __hyro_require({id});"));
+                    }
+                    None => requires.push(format!(
+                        "__hyro_export_star(__hyro_exports, __hyro_require({id}));"
+                    )),
+                }
+                edits.push((export.span, blank(code, export.span)));
+            }
+            _ => (),
+        }
+    }
+
+    // Exports of imported bindings are exported through the module they came from.
+    for (_, local) in &mut exports {
+        if let Some((_, _, replacement)) = imports.iter().find(|(_, name, _)| name == local) {
+            *local = replacement.clone();
+        }
+    }
+
+    let statements = edits.clone();
+    let nodes = semantic.nodes();
+    for (symbol, name, replacement) in &imports {
+        for reference in semantic.scoping().get_resolved_references(*symbol) {
+            let span = nodes.kind(reference.node_id()).span();
+            if statements
+                .iter()
+                .any(|(s, _)| s.start <= span.start && span.end <= s.end)
+            {
+                continue;
+            }
+
+            let replacement = match nodes.parent_kind(reference.node_id()) {
+                AstKind::ObjectProperty(property) if property.shorthand => {
+                    format!("{name}: {replacement}")
+                }
+                _ => replacement.clone(),
+            };
+            edits.push((span, replacement));
+        }
+    }
+
+    let getters: Vec<String> = exports
+        .iter()
+        .map(|(name, local)| format!("{}: () => {local}", serde_json::json!(name)))
+        .collect();
+
+    // Exports are defined before anything is required, so that circular imports see them.
+    let prelude = format!(
+        "__hyro_export(__hyro_exports, {{ {} }}); {}",
+        getters.join(", "),
+        requires.join(" ")
+    );
+
+    let mut linked = String::new();
+    let mut written = 0;
+    edits.sort_by_key(|(span, _)| (span.start, span.end));
+    for (span, replacement) in edits {
+        let (start, end) = (span.start as usize, span.end as usize);
+        if start < written {
+            continue;
+        }
+
+        linked += &code[written..start];
+        linked += &replacement;
+        written = end;
+    }
+    linked += &code[written..];
+
+    Ok((prelude, linked))
+}
+
+/// Removes a statement, keeping the lines it spanned.
+fn blank(code: &str, span: Span) -> String {
+    "\n".repeat(span.source_text(code).matches('\n').count())
+}
+
+/// A property of an imported module, like `__hyro_0["name"]`.
+fn member(module: &str, name: &str) -> String {
+    format!("{module}[{}]", serde_json::json!(name))
+}
+
+/// Resolves a relative import, trying common extensions when they're left out, and `.ts` files
+/// imported as `.js`.
+fn resolve(specifier: &str, importer: &Path) -> Option<PathBuf> {
+    if !specifier.starts_with("./") && !specifier.starts_with("../") {
+        return None;
+    }
+
+    let path = crate::embedded::normalize(&importer.parent()?.join(specifier));
+    let exists = |path: &PathBuf| crate::embedded::get(path).is_some() || path.is_file();
+
+    let mut candidates = vec![path.clone()];
+    candidates.extend(EXTENSIONS.iter().map(|ext| {
+        let mut with_extension = path.clone().into_os_string();
+        with_extension.push(format!(".{ext}"));
+        PathBuf::from(with_extension)
+    }));
+    candidates.extend(
+        EXTENSIONS
+            .iter()
+            .map(|ext| path.join(format!("index.{ext}"))),
+    );
+    if path.extension().is_some_and(|ext| ext == "js") {
+        candidates.extend(["ts", "tsx"].iter().map(|ext| path.with_extension(ext)));
+    }
+
+    candidates.into_iter().find(exists)
+}
+
+#[cfg(not(debug_assertions))]
+fn minify(entry: &Path, code: &str) -> Result<String, BundleJSError> {
+    use oxc::minifier::{Minifier, MinifierOptions};
+
+    let allocator = Allocator::default();
+    let parsed = Parser::new(&allocator, code, SourceType::cjs()).parse();
+    if let Some(error) = parsed.errors.first() {
+        return Err(BundleJSError::new(entry, error.message.to_string()));
+    }
+
+    let mut program = parsed.program;
+    let minified = Minifier::new(MinifierOptions::default()).minify(&allocator, &mut program);
+
+    Ok(Codegen::new()
+        .with_options(CodegenOptions::minify())
+        .with_scoping(minified.scoping)
+        .build(&program)
+        .code)
+}
+
+/// Bundles a script, recording any error so that it shows up in the HMR error overlay.
+#[cfg(debug_assertions)]
+pub(crate) fn transform_script(entry: &Path) -> Result<String, crate::overlay::CompileError> {
+    bundle_js(entry).map_err(|e| {
+        error!("Error while bundling {}: {e}", entry.display());
+
+        let error = crate::overlay::CompileError::js(&entry.display().to_string(), &e);
+        crate::overlay::ERRORS
+            .lock()
+            .insert(error.id.clone(), error.clone());
+        error
+    })
+}
+
+/// Bundles a script once, logging any error. Its message is served in place of the script.
+#[cfg(not(debug_assertions))]
+pub(crate) fn release_script(entry: &Path) -> Result<String, String> {
+    bundle_js(entry).map_err(|e| {
+        error!("Error while bundling {}: {e}", entry.display());
+        e.to_string()
+    })
+}
+
+/// Bundles the script served at `endpoint`.
+#[cfg(debug_assertions)]
+pub(crate) fn script_at(endpoint: &str) -> Option<Result<String, crate::overlay::CompileError>> {
+    let entry = SCRIPTS
+        .read()
+        .iter()
+        .find(|(e, _)| e == endpoint)
+        .map(|(_, entry)| entry.clone())?;
+
+    Some(transform_script(&entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundled(entry: &str) -> String {
+        bundle(Path::new(&format!("tests/scripts/{entry}")))
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn types_are_stripped() {
+        let code = bundled("main.ts");
+        assert!(!code.contains("interface Greeting"));
+        assert!(!code.contains(": string"));
+        assert!(code.contains("function welcome({ name, times })"));
+        assert!(bundle_js(Path::new("tests/scripts/main.ts")).is_ok());
+    }
+
+    #[test]
+    fn imports_are_required_from_their_module() {
+        let code = bundled("main.ts");
+        assert!(code.contains("const __hyro_0 = __hyro_require(1);"));
+        assert!(code.contains(r#"__hyro_0["default"](name).repeat(__hyro_0["double"](times))"#));
+    }
+
+    #[test]
+    fn anonymous_default_exports_are_named() {
+        let code = bundled("math.ts");
+        assert!(code.contains("const __hyro_default = function(name) {"));
+        assert!(code.contains(r#""default": () => __hyro_default"#));
+        assert!(code.contains(r#""double": () => double"#));
+    }
+
+    #[test]
+    fn reexports_are_exported_through_their_module() {
+        let code = bundled("reexports.ts");
+        assert!(code.contains(r#""twice": () => __hyro_0["double"]"#));
+        assert!(code.contains("__hyro_export_star(__hyro_exports, __hyro_require(2));"));
+        assert!(code.contains(r#""geometry": () => __hyro_2"#));
+        // Both exports of `shapes.ts` share one module.
+        assert_eq!(code.matches("\n__hyro_define(").count(), 3);
+    }
+
+    #[test]
+    fn circular_imports_share_a_module() {
+        let code = bundled("cycle_a.ts");
+        assert_eq!(code.matches("\n__hyro_define(").count(), 2);
+        assert!(code.contains("const __hyro_0 = __hyro_require(1);"));
+        assert!(code.contains("const __hyro_0 = __hyro_require(0);"));
+        assert!(code.contains(r#"const loop = () => __hyro_0["a"]();"#));
+    }
+
+    #[test]
+    fn errors_point_into_their_file() {
+        let error = bundle_js(Path::new("tests/scripts/broken.ts")).unwrap_err();
+        assert!(error.path.ends_with("broken.ts"));
+        assert_eq!(error.offset, Some(23));
+
+        let error = bundle_js(Path::new("tests/scripts/missing.ts")).unwrap_err();
+        assert!(error.path.ends_with("missing.ts"));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn source_maps_point_to_the_original_lines() {
+        let (code, map) = bundle(Path::new("tests/scripts/main.ts")).unwrap();
+        let lookup_table = map.generate_lookup_table();
+
+        let original = |text: &str| {
+            let (line, column) = code
+                .lines()
+                .enumerate()
+                .find_map(|(line, content)| Some((line, content.find(text)?)))
+                .unwrap();
+            let token = map
+                .lookup_token(&lookup_table, line as u32, column as u32)
+                .unwrap();
+            let source = map.get_source(token.get_source_id().unwrap()).unwrap();
+            (
+                source.rsplit('/').next().unwrap().to_string(),
+                token.get_src_line(),
+            )
+        };
+
+        assert_eq!(original("return __hyro_0"), ("main.ts".to_string(), 8));
+        assert_eq!(original("return `hello"), ("math.ts".to_string(), 1));
+        assert_eq!(original("const double"), ("math.ts".to_string(), 4));
+    }
+}
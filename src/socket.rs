@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::framework::*;
+use crate::runtime::*;
+use crate::{Error, Htmx};
+
+type SocketFuture = Pin<Box<dyn Future<Output = Result<String, Error>> + Send>>;
+pub(crate) type SocketHandler = Arc<dyn Fn(SocketMessage) -> SocketFuture + Send + Sync>;
+
+/// Fragments waiting to be sent to a connection. Connections that fall this far behind miss
+/// the fragments sent to them in the meantime.
+const CAPACITY: usize = 64;
+
+struct Connection {
+    sender: MpscSender<String>,
+    groups: HashSet<String>,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Every open connection to a websocket served with `RouterExt::with_websocket`, by its id.
+static CONNECTIONS: Lazy<Mutex<HashMap<u64, Connection>>> = Lazy::new(Default::default);
+
+/// A form sent over a websocket by htmx's `ws-send`, along with the connection it came from.
+pub struct SocketMessage {
+    /// The values of the form, like [Template::form](crate::Template::form).
+    pub form: HashMap<String, String>,
+    /// The htmx headers sent along with the form.
+    pub htmx: Htmx,
+    pub socket: Socket,
+}
+
+/// A connection to a websocket served with `RouterExt::with_websocket`, which can be sent
+/// fragments at any time, even after the message that it came with was handled.
+#[derive(Debug, Clone)]
+pub struct Socket {
+    id: u64,
+}
+
+impl Socket {
+    /// Identifies the connection for as long as the program runs.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Adds the connection to a group, so that it receives what's [broadcast] to the group.
+    /// Every connection is in the group named after the endpoint it's connected to.
+    pub fn join(&self, group: &str) {
+        if let Some(connection) = CONNECTIONS.lock().get_mut(&self.id) {
+            connection.groups.insert(group.to_string());
+        }
+    }
+
+    pub fn leave(&self, group: &str) {
+        if let Some(connection) = CONNECTIONS.lock().get_mut(&self.id) {
+            connection.groups.remove(group);
+        }
+    }
+
+    /// Sends a fragment to this connection alone. Does nothing once the connection is closed.
+    pub fn send<S: Into<String>>(&self, html: S) {
+        if let Some(connection) = CONNECTIONS.lock().get_mut(&self.id) {
+            mpsc_try_send(&mut connection.sender, html.into());
+        }
+    }
+}
+
+/// Sends a fragment to every connection in `group`, like everyone connected to `/dashboard`:
+///
+/// ```ignore
+/// hyro::broadcast("/dashboard", hyro::render_oob("visitors", "visitors", context!(count))?);
+/// ```
+pub fn broadcast<S: Into<String>>(group: &str, html: S) {
+    let html = html.into();
+
+    for connection in CONNECTIONS.lock().values_mut() {
+        if connection.groups.contains(group) {
+            mpsc_try_send(&mut connection.sender, html.clone());
+        }
+    }
+}
+
+pub(crate) fn handler<F, Fut>(handler: F) -> SocketHandler
+where
+    F: Fn(SocketMessage) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<String, Error>> + Send + 'static,
+{
+    Arc::new(move |message| Box::pin(handler(message)))
+}
+
+/// Handles a connection until it's closed, replying to each form that's sent with what the
+/// handler renders, and forwarding the fragments that are sent to the connection.
+pub(crate) async fn serve(mut websocket: WebSocket, endpoint: String, handler: SocketHandler) {
+    let (sender, mut receiver) = mpsc_channel(CAPACITY);
+    let socket = Socket {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+    };
+    CONNECTIONS.lock().insert(
+        socket.id,
+        Connection {
+            sender,
+            groups: HashSet::from([endpoint.clone()]),
+        },
+    );
+
+    enum Next<M> {
+        Received(Option<M>),
+        Sent(Option<String>),
+    }
+
+    loop {
+        let next = race(
+            async { Next::Received(websocket_recv(&mut websocket).await) },
+            async { Next::Sent(receiver.next().await) },
+        )
+        .await;
+
+        let html = match next {
+            Next::Received(Some(Ok(message))) => {
+                let Some(text) = websocket_try_as_text(message) else {
+                    continue;
+                };
+                let Some(message) = parse(&text, &socket) else {
+                    error!("(WS) {endpoint}: expected a form sent with `ws-send`, got {text}");
+                    continue;
+                };
+
+                match handler(message).await {
+                    Ok(html) => html,
+                    Err(e) => {
+                        error!("(WS) {endpoint}: {e}");
+                        continue;
+                    }
+                }
+            }
+            Next::Sent(Some(html)) => html,
+            Next::Received(_) | Next::Sent(None) => break,
+        };
+
+        if !html.is_empty() && websocket.send(websocket_message_text(html)).await.is_err() {
+            break;
+        }
+    }
+
+    CONNECTIONS.lock().remove(&socket.id);
+}
+
+/// htmx sends the form's values as JSON, with its request headers under `HEADERS`.
+fn parse(text: &str, socket: &Socket) -> Option<SocketMessage> {
+    let serde_json::Value::Object(mut values) = serde_json::from_str(text).ok()? else {
+        return None;
+    };
+
+    let headers = values.remove("HEADERS").unwrap_or_default();
+    let htmx = Htmx::from_headers(|name| headers.get(name).and_then(|value| value.as_str()));
+
+    // Fields with several values, like a multiple select, keep the last one, as they would in a
+    // query string.
+    let form = values
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let value = match value {
+                serde_json::Value::Array(values) => values.into_iter().last()?,
+                value => value,
+            };
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                value => value.to_string(),
+            };
+            Some((name, value))
+        })
+        .collect();
+
+    Some(SocketMessage {
+        form,
+        htmx,
+        socket: socket.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forms_are_parsed_along_with_their_htmx_headers() {
+        let text = r#"{
+            "message": "Hello",
+            "tags": ["rust", "htmx"],
+            "count": 3,
+            "HEADERS": {
+                "HX-Request": "true",
+                "HX-Trigger": "chat-form",
+                "HX-Trigger-Name": null,
+                "HX-Target": "messages",
+                "HX-Current-URL": "http://localhost:1380/chat"
+            }
+        }"#;
+        let message = parse(text, &Socket { id: 7 }).unwrap();
+
+        assert_eq!(message.form.len(), 3);
+        assert_eq!(message.form["message"], "Hello");
+        assert_eq!(message.form["tags"], "htmx");
+        assert_eq!(message.form["count"], "3");
+
+        assert!(message.htmx.request);
+        assert_eq!(message.htmx.trigger.as_deref(), Some("chat-form"));
+        assert_eq!(message.htmx.trigger_name, None);
+        assert_eq!(message.htmx.target.as_deref(), Some("messages"));
+        assert_eq!(
+            message.htmx.current_url.as_deref(),
+            Some("http://localhost:1380/chat")
+        );
+        assert_eq!(message.socket.id(), 7);
+    }
+
+    #[test]
+    fn only_json_objects_are_forms() {
+        assert!(parse("Hello", &Socket { id: 0 }).is_none());
+        assert!(parse(r#"["Hello"]"#, &Socket { id: 0 }).is_none());
+
+        let message = parse("{}", &Socket { id: 0 }).unwrap();
+        assert!(message.form.is_empty());
+        assert!(!message.htmx.request);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use minijinja::value::Value;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use crate::runtime::*;
+use crate::Error;
+
+type Channel = (BroadcastSender<Arc<Event>>, BroadcastReceiver<Arc<Event>>);
+
+/// Clients that fall further behind than this miss the oldest events.
+const CAPACITY: usize = 64;
+
+/// Event streams keyed by the endpoint they're served at. Created by whichever comes first: the
+/// stream being served, or an event being published to it.
+static CHANNELS: Lazy<RwLock<HashMap<String, Channel>>> = Lazy::new(Default::default);
+
+/// A rendered template, sent to every client subscribed to an event stream.
+#[derive(Debug)]
+pub(crate) struct Event {
+    pub(crate) name: String,
+    pub(crate) data: String,
+}
+
+/// Renders a template and sends it to every client connected to the event stream at `endpoint`,
+/// as an event named `event`. Event streams are served with `RouterExt::with_event_stream`.
+///
+/// ```ignore
+/// hyro::publish("/events", "todo", "todo", context!(todo))?;
+/// ```
+///
+/// With htmx's SSE extension, the event is swapped into an element that listens for it:
+///
+/// ```html
+/// <ul hx-ext="sse" sse-connect="/events" sse-swap="todo" hx-swap="beforeend"></ul>
+/// ```
+///
+/// The template is rendered once, no matter how many clients are connected. Clients that connect
+/// later don't receive events that were published before they connected.
+pub fn publish(endpoint: &str, event: &str, template: &str, context: Value) -> Result<(), Error> {
+    let data = crate::render::render_standalone(template, context)?;
+    let event = Event {
+        // A line break would end the event's name, and split the rest into a field of its own.
+        name: event.replace(['\r', '\n'], ""),
+        // Lines of an event can only be separated by `\n` once it's sent.
+        data: data.replace("\r\n", "\n").replace('\r', "\n"),
+    };
+
+    with_channel(endpoint, |channel| {
+        broadcast_send(&channel.0, Arc::new(event))
+    });
+    Ok(())
+}
+
+/// Subscribes to the events published to `endpoint` from now on.
+pub(crate) fn subscribe(endpoint: &str) -> BroadcastReceiver<Arc<Event>> {
+    with_channel(endpoint, broadcast_subscribe)
+}
+
+fn with_channel<T>(endpoint: &str, f: impl FnOnce(&Channel) -> T) -> T {
+    if let Some(channel) = CHANNELS.read().get(endpoint) {
+        return f(channel);
+    }
+
+    let mut channels = CHANNELS.write();
+    let channel = channels
+        .entry(endpoint.to_string())
+        .or_insert_with(|| broadcast_channel(CAPACITY));
+    f(channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_published_without_carriage_returns() {
+        crate::tests::use_test_templates();
+        let mut events = subscribe("/sse-tests");
+        publish(
+            "/sse-tests",
+            "new\r\ntodo\r",
+            "greeting",
+            minijinja::context!(name => "a\r\nb\rc"),
+        )
+        .unwrap();
+
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.name, "newtodo");
+        assert_eq!(event.data, "<p>Hello, a\nb\nc!</p>");
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
 pub use lightningcss;
@@ -5,9 +6,12 @@ pub use lightningcss::css_modules::{Config, Pattern};
 pub use lightningcss::stylesheet::{ParserFlags, ParserOptions};
 pub use lightningcss::targets::{Browsers, Features, Targets};
 
-use lightningcss::bundler::{Bundler, FileProvider};
+use lightningcss::bundler::{BundleErrorKind, Bundler, FileProvider, SourceProvider};
+use lightningcss::css_modules::{CssModuleExport, CssModuleReference};
+use lightningcss::error::{Error, PrinterErrorKind};
 use lightningcss::stylesheet::PrinterOptions;
 use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::RwLock;
 
 static DEFAULT_STYLE_OPTIONS: Lazy<ParserOptions> = Lazy::new(|| ParserOptions {
     flags: ParserFlags::NESTING | ParserFlags::CUSTOM_MEDIA,
@@ -29,34 +33,140 @@ static DEFAULT_STYLE_TARGETS: Lazy<Targets> = Lazy::new(|| Targets {
     ..Default::default()
 });
 
-pub(crate) static STYLE_FILE_PROVIDER: Lazy<FileProvider> = Lazy::new(FileProvider::new);
-#[cfg(not(debug_assertions))]
-pub(crate) static MAIN_CSS: OnceCell<String> = OnceCell::new();
-#[cfg(debug_assertions)]
-pub(crate) static STYLE_MAIN_FILE: OnceCell<PathBuf> = OnceCell::new();
+/// Reads stylesheets embedded with `hyro::embed!`, falling back to the file system.
+pub(crate) struct StyleProvider(FileProvider);
+
+impl SourceProvider for StyleProvider {
+    type Error = std::io::Error;
+
+    fn read<'a>(&'a self, file: &Path) -> Result<&'a str, Self::Error> {
+        match crate::embedded::get(file) {
+            Some(source) => Ok(source),
+            None => self.0.read(file),
+        }
+    }
+
+    fn resolve(&self, specifier: &str, originating_file: &Path) -> Result<PathBuf, Self::Error> {
+        self.0.resolve(specifier, originating_file)
+    }
+}
+
+pub(crate) static STYLE_FILE_PROVIDER: Lazy<StyleProvider> =
+    Lazy::new(|| StyleProvider(FileProvider::new()));
 pub(crate) static STYLE_OPTIONS: OnceCell<ParserOptions> = OnceCell::new();
 pub(crate) static STYLE_TARGETS: OnceCell<Targets> = OnceCell::new();
 
+/// Every stylesheet served with `with_bundled_css` or `with_css_modules`, and the endpoint it's served at.
+pub(crate) static BUNDLES: Lazy<RwLock<Vec<(String, Bundle)>>> = Lazy::new(Default::default);
+
+/// The scoped class names of every CSS module, keyed by its path relative to its directory.
+pub(crate) static CSS_MODULES: Lazy<RwLock<HashMap<String, BTreeMap<String, String>>>> =
+    Lazy::new(Default::default);
+
+#[derive(Debug, Clone)]
+pub(crate) enum Bundle {
+    /// A stylesheet, bundled with everything it `@import`s.
+    File(PathBuf),
+    /// A directory of CSS modules, each of which has its class names scoped to itself.
+    Modules(PathBuf),
+}
+
+impl Bundle {
+    /// The file or directory the bundle is built from, which also identifies its errors.
+    #[cfg(debug_assertions)]
+    pub(crate) fn path(&self) -> &Path {
+        match self {
+            Self::File(path) | Self::Modules(path) => path,
+        }
+    }
+
+    pub(crate) fn transform<'a>(&self) -> Result<String, TransformCSSError<'a>> {
+        match self {
+            // Stylesheets are bundled at compile time with the default style options, so they're
+            // bundled again when the options were changed.
+            #[cfg(not(debug_assertions))]
+            Self::File(path) if STYLE_OPTIONS.get().is_none() && STYLE_TARGETS.get().is_none() => {
+                match crate::embedded::bundled(path) {
+                    Some(css) => Ok(css.to_string()),
+                    None => transform_css(path),
+                }
+            }
+            Self::File(path) => transform_css(path),
+            Self::Modules(dir) => transform_css_modules(dir),
+        }
+    }
+}
+
+/// Registers a bundle, returning an error if the endpoint is already taken.
+pub(crate) fn add_bundle(endpoint: &str, bundle: Bundle) -> Result<(), String> {
+    let mut bundles = BUNDLES.write();
+    if bundles.iter().any(|(e, _)| e == endpoint) {
+        return Err(format!("A stylesheet is already served at {endpoint}"));
+    }
+
+    bundles.push((endpoint.to_string(), bundle));
+    Ok(())
+}
+
 #[derive(Debug)]
 pub(crate) enum TransformCSSError<'a> {
-    BundleError(lightningcss::bundler::BundleErrorKind<'a, std::io::Error>),
-    PrinterError(lightningcss::error::PrinterErrorKind),
+    BundleError(Error<BundleErrorKind<'a, std::io::Error>>),
+    PrinterError(Error<PrinterErrorKind>),
+}
+
+#[cfg(debug_assertions)]
+impl TransformCSSError<'_> {
+    pub fn location(&self) -> Option<&lightningcss::error::ErrorLocation> {
+        match self {
+            Self::BundleError(e) => e.loc.as_ref(),
+            Self::PrinterError(e) => e.loc.as_ref(),
+        }
+    }
+
+    /// The error message, without the location.
+    pub fn message(&self) -> String {
+        match self {
+            Self::BundleError(e) => e.kind.to_string(),
+            Self::PrinterError(e) => e.kind.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for TransformCSSError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BundleError(e) => write!(f, "{e}"),
+            Self::PrinterError(e) => write!(f, "{e}"),
+        }
+    }
 }
 
 /// Utility function for bundling and minifying CSS.
-pub(crate) fn transform_css<'a>(path: &PathBuf) -> Result<String, TransformCSSError<'a>> {
+pub(crate) fn transform_css<'a>(path: &Path) -> Result<String, TransformCSSError<'a>> {
+    transform(path, None).map(|(code, _)| code)
+}
+
+/// Bundles and minifies a stylesheet, scoping its names when `css_modules` is set.
+/// Returns the CSS, along with the scoped names of a CSS module.
+fn transform<'a>(
+    path: &Path,
+    css_modules: Option<Config<'static>>,
+) -> Result<(String, BTreeMap<String, String>), TransformCSSError<'a>> {
     // 1: Initialize the bundler state
     let mut bundler = Bundler::new(
         &*STYLE_FILE_PROVIDER,
         None,
-        STYLE_OPTIONS
-            .get()
-            .unwrap_or(&DEFAULT_STYLE_OPTIONS)
-            .clone(),
+        ParserOptions {
+            css_modules,
+            ..STYLE_OPTIONS
+                .get()
+                .unwrap_or(&DEFAULT_STYLE_OPTIONS)
+                .clone()
+        },
     );
 
     // 2: Bundle the CSS by following @import statements
-    match bundler.bundle(Path::new(&path)) {
+    match bundler.bundle(path) {
         Ok(stylesheet) => {
             // 3: Since step 2 produced a rust-native stylesheet structure, we convert it back to CSS.
             let printed = stylesheet.to_css(PrinterOptions {
@@ -65,12 +175,98 @@ pub(crate) fn transform_css<'a>(path: &PathBuf) -> Result<String, TransformCSSEr
                 ..Default::default()
             });
 
-            // 4: Only return the serialized CSS in the .code field
+            // 4: Only return the serialized CSS in the .code field, and the names of the exports
             match printed {
-                Ok(printed) => Ok(printed.code),
-                Err(e) => Err(TransformCSSError::PrinterError(e.kind)),
+                Ok(printed) => Ok((
+                    printed.code,
+                    printed
+                        .exports
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(name, export)| (name, class_of_export(export)))
+                        .collect(),
+                )),
+                Err(e) => Err(TransformCSSError::PrinterError(e)),
             }
         }
-        Err(e) => Err(TransformCSSError::BundleError(e.kind)),
+        Err(e) => Err(TransformCSSError::BundleError(e)),
+    }
+}
+
+/// The classes an element needs for a CSS module export, including the ones it `composes`.
+fn class_of_export(export: CssModuleExport) -> String {
+    export
+        .composes
+        .into_iter()
+        .filter_map(|reference| match reference {
+            CssModuleReference::Local { name } | CssModuleReference::Global { name } => Some(name),
+            CssModuleReference::Dependency { .. } => None,
+        })
+        .fold(export.name, |class, name| format!("{class} {name}"))
+}
+
+/// Transforms every stylesheet in `dir` as a CSS module, recording their scoped names for
+/// `css_module()`. The pattern for scoped names comes from `css_modules` in the style options.
+fn transform_css_modules<'a>(dir: &Path) -> Result<String, TransformCSSError<'a>> {
+    let config = STYLE_OPTIONS
+        .get()
+        .and_then(|options| options.css_modules.clone())
+        .unwrap_or_default();
+
+    let mut code = String::new();
+    for name in css_files_in(dir) {
+        let (css, exports) = transform(&dir.join(&name), Some(config.clone()))?;
+        code += &css;
+        CSS_MODULES.write().insert(name, exports);
     }
+
+    Ok(code)
+}
+
+/// Lists the stylesheets inside of `dir`, relative to `dir`, preferring embedded ones.
+fn css_files_in(dir: &Path) -> Vec<String> {
+    crate::embedded::list(dir)
+        .into_iter()
+        .filter(|name| name.ends_with(".css"))
+        .collect()
+}
+
+/// The `css_module` template function, which returns the scoped class names of a CSS module,
+/// e.g. `css_module("card.css").title`.
+pub(crate) fn css_module(name: String) -> Result<minijinja::value::Value, minijinja::Error> {
+    match CSS_MODULES.read().get(&name) {
+        Some(exports) => Ok(minijinja::value::Value::from_serializable(exports)),
+        None => Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("no CSS module named {name}"),
+        )),
+    }
+}
+
+/// Bundles a stylesheet, recording any error so that it shows up in the HMR error overlay.
+#[cfg(debug_assertions)]
+pub(crate) fn transform_bundle(bundle: &Bundle) -> Result<String, crate::overlay::CompileError> {
+    let path = bundle.path();
+
+    bundle.transform().map_err(|e| {
+        error!("Error while bundling {}: {e}", path.display());
+
+        let error = crate::overlay::CompileError::css(&path.display().to_string(), &e);
+        crate::overlay::ERRORS
+            .lock()
+            .insert(error.id.clone(), error.clone());
+        error
+    })
+}
+
+/// Bundles the stylesheet served at `endpoint`.
+#[cfg(debug_assertions)]
+pub(crate) fn bundle_at(endpoint: &str) -> Option<Result<String, crate::overlay::CompileError>> {
+    let bundle = BUNDLES
+        .read()
+        .iter()
+        .find(|(e, _)| e == endpoint)
+        .map(|(_, bundle)| bundle.clone())?;
+
+    Some(transform_bundle(&bundle))
 }
@@ -1,10 +1,14 @@
 use minijinja::value::Value;
 use once_cell::sync::OnceCell;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 
-use crate::context;
 use crate::framework::*;
+use crate::render::Part;
+use crate::{Error, Htmx};
 
 pub(crate) static TEMPLATE_EXTENSION: OnceCell<String> = OnceCell::new();
 
@@ -15,58 +19,283 @@ pub(crate) fn template_extension() -> &'static str {
     }
 }
 
-pub struct Template {
+/// A template for the current endpoint, along with the form data (or query) it was requested with.
+///
+/// By default the form is a map of strings. Any type implementing [Deserialize](serde::Deserialize)
+/// can be used instead, like `Template<TodoForm>`, in which case a form that doesn't deserialize is
+/// rejected with a 422.
+pub struct Template<F = HashMap<String, String>> {
     pub path: String,
-    pub form: HashMap<String, String>,
+    pub form: F,
+    /// The htmx headers of the request, available to the template as `htmx`.
+    pub htmx: Htmx,
+    /// The parameters of the matched route, e.g. `id` for `/users/:id`, available to the template
+    /// as `params`.
+    pub params: HashMap<String, String>,
 }
 
-impl Template {
+impl<F: Serialize> Template<F> {
+    /// Renders the template, serving an empty page if rendering fails.
+    /// Use [Template::try_render] to handle the error instead.
     pub fn render(self, context: Value) -> RenderedTemplate {
-        if context.is_undefined() {
-            if self.form.is_empty() {
-                crate::render::render(self.path, context)
-            } else {
-                crate::render::render(self.path, context!(form => self.form))
-            }
-        } else {
-            let mut context = context
-                .try_iter()
-                .unwrap()
-                .fold(HashMap::new(), |mut h, k| {
-                    h.insert(
-                        k.as_str().unwrap().to_string(),
-                        context.get_item(&k).unwrap(),
-                    );
-                    h
-                });
-
-            if !context.contains_key("form") {
-                context.insert("form".into(), self.form.into());
-            }
+        self.try_render(context)
+            .unwrap_or_else(|_| into_rendered_template(Cow::Borrowed("")))
+    }
+
+    /// Renders the template, returning an [Error] if the template is missing or fails to render.
+    /// The error can be returned from a handler to respond with a 404 or 500.
+    ///
+    /// When htmx requests a partial update (`HX-Request` without `HX-Boosted`) with a `Hyro-Block`
+    /// header, and the template defines that block, only the block is rendered. Requests without
+    /// the header always get the whole page.
+    pub fn try_render(self, context: Value) -> Result<RenderedTemplate, Error> {
+        let part = self.automatic_part();
+        let (path, context) = self.into_context(context);
+
+        crate::render::render(path, context, part)
+    }
+
+    /// Renders another template in place of the endpoint's own, like `errors/404` or `partials/row`,
+    /// with the same form, htmx headers and route parameters. Serves an empty page if rendering fails.
+    pub fn render_as(self, template: &str, context: Value) -> RenderedTemplate {
+        self.try_render_as(template, context)
+            .unwrap_or_else(|_| into_rendered_template(Cow::Borrowed("")))
+    }
+
+    /// Renders another template in place of the endpoint's own, returning an [Error] if the
+    /// template is missing or fails to render. Partial htmx requests with a `Hyro-Block` header
+    /// render that block, like [Template::try_render].
+    pub fn try_render_as(self, template: &str, context: Value) -> Result<RenderedTemplate, Error> {
+        let part = self.automatic_part();
+        let (path, context) = self.into_context(context);
+
+        crate::render::render_as(&path, template, context, part)
+    }
+
+    /// Renders a single `{% block %}` of the template, without the rest of the page or
+    /// the layout it extends. Serves an empty page if rendering fails.
+    pub fn render_block(self, block: &str, context: Value) -> RenderedTemplate {
+        self.try_render_block(block, context)
+            .unwrap_or_else(|_| into_rendered_template(Cow::Borrowed("")))
+    }
+
+    /// Renders a single `{% block %}` of the template, returning an [Error] if the template is
+    /// missing, fails to render, or doesn't define the block.
+    pub fn try_render_block(self, block: &str, context: Value) -> Result<RenderedTemplate, Error> {
+        let (path, context) = self.into_context(context);
+
+        crate::render::render(path, context, Part::Block(block.into()))
+    }
+
+    /// Renders the template along with other templates that htmx swaps in out of band, to update
+    /// several parts of the page with one response, like a list and its counter. Serves an empty
+    /// page if rendering fails.
+    ///
+    /// ```ignore
+    /// template.render_with_oob(context!(todo), [("splash", context!(num_done))])
+    /// ```
+    pub fn render_with_oob<'a, I>(self, context: Value, oob: I) -> RenderedTemplate
+    where
+        I: IntoIterator<Item = (&'a str, Value)>,
+    {
+        self.try_render_with_oob(context, oob)
+            .unwrap_or_else(|_| into_rendered_template(Cow::Borrowed("")))
+    }
+
+    /// Renders the template along with other templates that htmx swaps in out of band, returning
+    /// an [Error] if any of them is missing or fails to render.
+    ///
+    /// Each of the other templates is wrapped with `hx-swap-oob`, targeting the element whose id
+    /// is the template's file name, e.g. `counter` for `partials/counter`. They get the same form,
+    /// htmx headers and route parameters as the template.
+    pub fn try_render_with_oob<'a, I>(
+        self,
+        context: Value,
+        oob: I,
+    ) -> Result<RenderedTemplate, Error>
+    where
+        I: IntoIterator<Item = (&'a str, Value)>,
+    {
+        let fragments = oob
+            .into_iter()
+            .map(|(template, context)| {
+                let html =
+                    crate::render::render_standalone(template, self.request_context(context))?;
+                let target = template.rsplit('/').next().unwrap_or(template);
+                let target = target.split('.').next().unwrap_or(target);
+                Ok(crate::render::out_of_band(target, &html))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut html = from_rendered_template(self.try_render(context)?).into_owned();
+        html.extend(fragments);
+        Ok(into_rendered_template(Cow::Owned(html)))
+    }
+
+    /// Renders the template as it's being sent, instead of buffering the whole page first.
+    /// Serves an empty page if the template is missing or fails to compile.
+    pub fn render_stream(self, context: Value) -> StreamedTemplate {
+        self.try_render_stream(context)
+            .unwrap_or_else(|_| into_streamed_template(crate::runtime::mpsc_channel(1).1))
+    }
+
+    /// Renders the template as it's being sent, instead of buffering the whole page first.
+    ///
+    /// Only errors that happen before rendering starts are returned. Since the response has already
+    /// started by the time the template renders, later errors are logged and end the response early.
+    pub fn try_render_stream(self, context: Value) -> Result<StreamedTemplate, Error> {
+        let part = self.automatic_part();
+        let (path, context) = self.into_context(context);
+
+        crate::render::render_stream(path, context, part).map(into_streamed_template)
+    }
+
+    /// Partial htmx requests render the block they asked for, if the template defines it.
+    fn automatic_part(&self) -> Part {
+        match &self.htmx.block {
+            Some(block) if self.htmx.is_partial() => Part::BlockOrPage(block.clone()),
+            _ => Part::Page,
+        }
+    }
+
+    fn into_context(self, context: Value) -> (String, Value) {
+        let context = self.request_context(context);
+        (self.path, context)
+    }
+
+    /// Adds the form, htmx headers and route parameters to the context, unless the context
+    /// overrides them.
+    fn request_context(&self, context: Value) -> Value {
+        let mut context = match context.try_iter() {
+            Ok(keys) => keys.fold(HashMap::new(), |mut h, k| {
+                h.insert(
+                    k.as_str().unwrap().to_string(),
+                    context.get_item(&k).unwrap(),
+                );
+                h
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        context
+            .entry("form".into())
+            .or_insert_with(|| Value::from_serializable(&self.form));
+        context
+            .entry("htmx".into())
+            .or_insert_with(|| Value::from_serializable(&self.htmx));
+        context
+            .entry("params".into())
+            .or_insert_with(|| Value::from_serializable(&self.params));
+
+        context.into()
+    }
+}
+
+/// Renders a template outside of a request, like for an email or a server-sent event. The name is
+/// relative to the template directory, with or without the extension, e.g. `partials/row`.
+///
+/// Unlike [Template::render], the template doesn't get a `form`, `htmx` headers or `params`, and
+/// nothing is added for HMR.
+pub fn render(template: &str, context: Value) -> Result<String, Error> {
+    crate::render::render_standalone(template, context)
+}
+
+/// Like [render], but swapped by htmx into the element with the id `target` through
+/// `hx-swap-oob`, wherever it ends up in a response. Fragments sent over a websocket with htmx's
+/// `ws` extension are swapped this way.
+pub fn render_oob(target: &str, template: &str, context: Value) -> Result<String, Error> {
+    let html = crate::render::render_standalone(template, context)?;
+    Ok(crate::render::out_of_band(target, &html))
+}
 
-            crate::render::render(self.path, context.into())
+impl Template {
+    /// Forms are extracted (and replayed by HMR) as strings first, and then deserialized into
+    /// the type the handler asked for.
+    pub(crate) fn deserialize<F: DeserializeOwned>(self) -> Result<Template<F>, Error> {
+        let encoded = serde_urlencoded::to_string(&self.form).unwrap_or_default();
+        let deserializer =
+            serde_urlencoded::Deserializer::new(form_urlencoded::parse(encoded.as_bytes()));
+
+        match serde_path_to_error::deserialize(deserializer) {
+            Ok(form) => Ok(Template {
+                path: self.path,
+                form,
+                htmx: self.htmx,
+                params: self.params,
+            }),
+            Err(e) => {
+                let field = match e.path().to_string() {
+                    // Missing fields are reported on the parent, but the message names the field.
+                    path if path == "." => e.inner().to_string().split('`').nth(1).map(Into::into),
+                    path => Some(path),
+                };
+
+                Err(Error::InvalidForm {
+                    field,
+                    message: e.into_inner().to_string(),
+                })
+            }
         }
     }
 }
 
+/// The header that `hmr.js` sends with htmx and HMR requests, identifying the browser tab.
+#[cfg(debug_assertions)]
+pub(crate) const HMR_CLIENT_HEADER: &str = "HMR-Client";
+
+/// The cookie that `hmr.js` sets to the tab's id, for requests that htmx doesn't make, like
+/// submitting a plain form. Cookies are shared by every tab, so it holds the id of the tab that
+/// was last focused or interacted with, and a tab fetching in the background may send another's.
+#[cfg(debug_assertions)]
+const HMR_CLIENT_COOKIE: &str = "hyro-client";
+
+/// The tab a request came from, identified by its `HMR-Client` header, or else by its cookie.
+#[cfg(debug_assertions)]
+pub(crate) fn hmr_client<'a>(
+    header: Option<&'a str>,
+    cookies: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    header.or_else(|| {
+        cookies
+            .into_iter()
+            .flat_map(|cookies| cookies.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(name, value)| *name == HMR_CLIENT_COOKIE && !value.is_empty())
+            .map(|(_, value)| value)
+    })
+}
+
 #[cfg(debug_assertions)]
 pub(crate) fn template_hydrate(
-    ip: std::net::IpAddr,
+    client: Option<&str>,
     this_endpoint: String,
     form_from_request: HashMap<String, String>,
+    htmx: Htmx,
+    params: HashMap<String, String>,
 ) -> Template {
-    let mut forms = crate::TEMPLATES.forms.lock();
-    // 1: If this IP hasn't recorded any forms *at all*, create an empty history table.
-    forms.entry(ip).or_insert_with(Default::default);
+    // Requests that don't come from a tab running HMR, like navigating to a page, can't be
+    // re-rendered in place, so there's no history to keep for them.
+    let Some(client) = client else {
+        return Template {
+            path: this_endpoint,
+            form: form_from_request,
+            htmx,
+            params,
+        };
+    };
 
-    let mut ip_endpoint_history = forms.get(&ip).unwrap().lock();
+    let mut forms = crate::TEMPLATES.forms.lock();
+    // 1: If this tab hasn't recorded any forms *at all*, create an empty history table.
+    let mut client_forms = forms.entry(client.to_string()).or_default().lock();
+    client_forms.last_render = std::time::Instant::now();
+    let client_endpoint_history = &mut client_forms.endpoints;
 
-    // 2: If this IP has not recorded a form for *this endpoint*, create an empty history for this endpoint.
-    if !ip_endpoint_history.contains_key(&this_endpoint) {
-        ip_endpoint_history.insert(this_endpoint.to_string(), Default::default());
+    // 2: If this tab has not recorded a form for *this endpoint*, create an empty history for this endpoint.
+    if !client_endpoint_history.contains_key(&this_endpoint) {
+        client_endpoint_history.insert(this_endpoint.to_string(), Default::default());
     }
 
-    let form_history = ip_endpoint_history.get_mut(&this_endpoint).unwrap();
+    let form_history = client_endpoint_history.get_mut(&this_endpoint).unwrap();
 
     // 3: The clientside HMR will assign an index for each element created.
     //     when we request a reload, the client will give us the indexes for each element
@@ -76,21 +305,189 @@ pub(crate) fn template_hydrate(
         Some(oldest_outdated_element_id) => {
             // 4: If there's an existing index, that means we're re-rendering an existing element with HMR magic.
             //     Hypermedia relies on form data, so we'll reuse the existing form data so we don't have to re-submit it.
+            //     Elements old enough to have had their form evicted are rendered with the requested form instead.
+            let form = match form_history.get(oldest_outdated_element_id) {
+                Some(form) => form.clone(),
+                None => form_from_request,
+            };
+
             Template {
                 path: this_endpoint,
-                form: form_history.contents[oldest_outdated_element_id as usize].clone(),
+                form,
+                htmx,
+                params,
             }
         }
 
         None => {
             // 5: If there's nothing else in the queue, then we're rendering a new element!
             //     We'll save the requested form data instead and return the requested form.
-            form_history.contents.push(form_from_request.clone());
+            form_history.push(form_from_request.clone());
 
             Template {
                 path: this_endpoint,
                 form: form_from_request,
+                htmx,
+                params,
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minijinja::context;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Signup {
+        name: String,
+        age: u8,
+    }
+
+    fn template(form: &[(&str, &str)]) -> Template {
+        Template {
+            path: "/signup".into(),
+            form: form
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            htmx: Default::default(),
+            params: Default::default(),
+        }
+    }
+
+    #[test]
+    fn forms_deserialize_into_typed_structs() {
+        let template = template(&[("name", "Ferris"), ("age", "8")])
+            .deserialize::<Signup>()
+            .unwrap();
+        assert_eq!(template.path, "/signup");
+        assert_eq!(template.form.name, "Ferris");
+        assert_eq!(template.form.age, 8);
+    }
+
+    #[test]
+    fn invalid_fields_are_named() {
+        let Err(Error::InvalidForm { field, .. }) =
+            template(&[("name", "Ferris"), ("age", "eight")]).deserialize::<Signup>()
+        else {
+            panic!("expected an invalid form");
+        };
+        assert_eq!(field.as_deref(), Some("age"));
+
+        let Err(Error::InvalidForm { field, .. }) =
+            template(&[("age", "8")]).deserialize::<Signup>()
+        else {
+            panic!("expected an invalid form");
+        };
+        assert_eq!(field.as_deref(), Some("name"));
+    }
+
+    fn dashboard(htmx: Htmx) -> String {
+        crate::tests::use_test_templates();
+        let template: Template = Template {
+            path: "/dashboard".into(),
+            form: HashMap::new(),
+            htmx,
+            params: Default::default(),
+        };
+        let rendered = template.try_render(context!(name => "Ferris")).unwrap();
+        from_rendered_template(rendered).into_owned()
+    }
+
+    #[test]
+    fn partial_requests_render_the_page_unless_they_ask_for_a_block() {
+        let page = dashboard(Htmx {
+            request: true,
+            target: Some("sidebar".into()),
+            ..Default::default()
+        });
+        assert!(page.contains("<header>Dashboard</header>"), "{page}");
+        assert!(page.contains("Sidebar for Ferris</nav>"), "{page}");
+
+        let block = dashboard(Htmx {
+            request: true,
+            block: Some("sidebar".into()),
+            ..Default::default()
+        });
+        assert!(!block.contains("<header>"), "{block}");
+        assert!(block.contains("Sidebar for Ferris</nav>"), "{block}");
+    }
+
+    #[test]
+    fn blocks_are_only_rendered_for_partial_requests_that_define_them() {
+        let boosted = dashboard(Htmx {
+            request: true,
+            boosted: true,
+            block: Some("sidebar".into()),
+            ..Default::default()
+        });
+        assert!(boosted.contains("<header>Dashboard</header>"), "{boosted}");
+
+        let missing = dashboard(Htmx {
+            request: true,
+            block: Some("footer".into()),
+            ..Default::default()
+        });
+        assert!(missing.contains("<header>Dashboard</header>"), "{missing}");
+    }
+
+    #[test]
+    fn out_of_band_templates_follow_the_page() {
+        crate::tests::use_test_templates();
+        let template: Template = Template {
+            path: "/about".into(),
+            form: HashMap::new(),
+            htmx: Default::default(),
+            params: Default::default(),
+        };
+        let rendered = template
+            .try_render_with_oob(
+                context!(),
+                [
+                    ("_badge", context!(label => "new")),
+                    ("greeting.html.jinja2", context!(name => "Ferris")),
+                ],
+            )
+            .unwrap();
+        let html = from_rendered_template(rendered);
+
+        let page = html.find("About us</h1>").unwrap();
+        let badge = html
+            .find(
+                r#"<div id="_badge" hx-swap-oob="innerHTML"><span class="badge">new</span></div>"#,
+            )
+            .unwrap();
+        let greeting = html
+            .find(r#"<div id="greeting" hx-swap-oob="innerHTML"><p>Hello, Ferris!</p></div>"#)
+            .unwrap();
+        assert!(page < badge && badge < greeting, "{html}");
+        assert!(html.ends_with("</p></div>"), "{html}");
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn hmr_clients_are_identified_by_header_then_cookie() {
+        let cookies = ["theme=dark; hyro-client=cookie", "session=1"];
+        assert_eq!(hmr_client(Some("header"), cookies), Some("header"));
+        assert_eq!(hmr_client(None, cookies), Some("cookie"));
+        assert_eq!(
+            hmr_client(None, ["session=1", "hyro-client=second"]),
+            Some("second")
+        );
+        assert_eq!(hmr_client(None, ["hyro-client=; theme=dark"]), None);
+        assert_eq!(hmr_client(None, []), None);
+    }
+
+    #[test]
+    fn out_of_band_targets_are_escaped() {
+        crate::tests::use_test_templates();
+        assert_eq!(
+            render_oob(r#"a"b"#, "greeting", context!(name => "Ferris")).unwrap(),
+            r#"<div id="a&quot;b" hx-swap-oob="innerHTML"><p>Hello, Ferris!</p></div>"#
+        );
+        assert!(render_oob("greeting", "missing", context!()).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use minijinja::value::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::framework::RenderedTemplate;
+use crate::{context, Error, HtmxResponse, Template};
+
+/// Checks a form after it's been deserialized. Failed checks are added to `errors`, keyed by the
+/// name of the field they belong to.
+///
+/// ```ignore
+/// impl Validate for TodoForm {
+///     fn validate(&self, errors: &mut ValidationErrors) {
+///         if self.description.trim().is_empty() {
+///             errors.add("description", "Please describe the todo");
+///         }
+///     }
+/// }
+/// ```
+pub trait Validate {
+    fn validate(&self, errors: &mut ValidationErrors);
+
+    /// The template that renders the form, re-rendered with its `errors` when validation fails,
+    /// like `todo-edit`. Defaults to the template of the endpoint that the form was submitted to.
+    fn template() -> Option<&'static str> {
+        None
+    }
+}
+
+/// Error messages for each field of a form, available to templates as `errors`,
+/// e.g. `{{ errors.description }}`.
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(BTreeMap<String, String>);
+
+impl ValidationErrors {
+    /// Adds an error for `field`. Only the first error for each field is kept.
+    pub fn add<K: Into<String>, V: Into<String>>(&mut self, field: K, message: V) {
+        self.0.entry(field.into()).or_insert_with(|| message.into());
+    }
+
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.0.get(field).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// A template whose form was validated before reaching the handler. If the form is invalid, the
+/// form's [Validate::template] is rendered again with the submitted `form` and its `errors` instead.
+#[cfg(feature = "framework-axum")]
+pub struct Validated<F>(pub Template<F>);
+
+/// A form that failed to deserialize or validate, re-rendered into the template that renders it.
+///
+/// When the form was submitted by htmx from an element with an `id`, the response retargets
+/// that element and swaps it out entirely, so the form is replaced by its re-rendered self.
+/// htmx doesn't swap error responses, so these are served with a 200. Other clients get a 422.
+pub struct Invalid {
+    /// The template the form was submitted to, with the form as it was submitted.
+    template: Box<Template<Value>>,
+    /// Rendered in place of the endpoint's own template, see [Validate::template].
+    render_as: Option<String>,
+    errors: ValidationErrors,
+}
+
+impl Invalid {
+    pub fn errors(&self) -> &ValidationErrors {
+        &self.errors
+    }
+
+    /// Re-renders the form with another template than [Validate::template].
+    pub fn render_as(mut self, template: &str) -> Self {
+        self.render_as = Some(template.to_string());
+        self
+    }
+
+    pub(crate) fn status(&self) -> u16 {
+        if self.template.htmx.request {
+            200
+        } else {
+            422
+        }
+    }
+
+    pub(crate) fn response(&self) -> HtmxResponse {
+        let htmx = &self.template.htmx;
+        match &htmx.trigger {
+            Some(id) if htmx.request => HtmxResponse::new()
+                .retarget(format!("#{id}"))
+                .reswap("outerHTML"),
+            _ => HtmxResponse::new(),
+        }
+    }
+
+    pub(crate) fn render(&self) -> Result<RenderedTemplate, Error> {
+        let template = Template {
+            path: self.template.path.clone(),
+            form: self.template.form.clone(),
+            htmx: self.template.htmx.clone(),
+            params: self.template.params.clone(),
+        };
+        let context = context!(errors => self.errors);
+
+        match &self.render_as {
+            Some(name) => template.try_render_as(name, context),
+            None => template.try_render(context),
+        }
+    }
+}
+
+impl Template {
+    /// Deserializes and validates the form. Fields that fail to deserialize are reported like any
+    /// other validation error, so that they can be shown next to the field.
+    pub(crate) fn validate<F: DeserializeOwned + Validate>(self) -> Result<Template<F>, Invalid> {
+        let submitted = Box::new(Template {
+            path: self.path.clone(),
+            form: Value::from_serializable(&self.form),
+            htmx: self.htmx.clone(),
+            params: self.params.clone(),
+        });
+        let mut errors = ValidationErrors::default();
+
+        match self.deserialize::<F>() {
+            Ok(template) => {
+                template.form.validate(&mut errors);
+                if errors.is_empty() {
+                    return Ok(template);
+                }
+            }
+            // Errors that don't belong to a field are shown as `errors.form`.
+            Err(Error::InvalidForm { field, message }) => {
+                errors.add(field.unwrap_or_else(|| "form".into()), message)
+            }
+            Err(e) => errors.add("form", e.to_string()),
+        }
+
+        Err(Invalid {
+            template: submitted,
+            render_as: F::template().map(str::to_string),
+            errors,
+        })
+    }
+}
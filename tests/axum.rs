@@ -0,0 +1,267 @@
+#![cfg(feature = "framework-axum")]
+
+use axum::body::Body;
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::routing::{get, post};
+use axum::Router;
+use hyro::prelude::*;
+use hyro::{context, Htmx, HtmxResponse, Template, Validate, Validated, ValidationErrors};
+use serde::Deserialize;
+use tower::ServiceExt;
+
+#[derive(Deserialize)]
+struct Signup {
+    name: String,
+    age: u8,
+}
+
+async fn signup(template: Template<Signup>) -> String {
+    format!("{} is {}", template.form.name, template.form.age)
+}
+
+#[derive(Deserialize)]
+struct Todo {
+    description: String,
+}
+
+impl Validate for Todo {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        if self.description.trim().is_empty() {
+            errors.add("description", "Please describe your todo");
+        }
+    }
+}
+
+async fn create_todo(Validated(template): Validated<Todo>) -> String {
+    format!("Created {}", template.form.description)
+}
+
+async fn target(htmx: Htmx) -> (HtmxResponse, String) {
+    (
+        HtmxResponse::new().redirect("/login").trigger("saved"),
+        format!("{:?} from {:?}", htmx.target, htmx.trigger),
+    )
+}
+
+#[derive(Deserialize)]
+struct Badge {
+    label: String,
+}
+
+async fn team(template: Template) -> axum::response::Html<std::borrow::Cow<'static, str>> {
+    template.render(context!())
+}
+
+fn app() -> Router {
+    let _ = hyro::config::set_template_dir("tests/templates");
+    hyro::component(
+        "_badge",
+        |badge: Badge| context!(label => badge.label.to_uppercase()),
+    );
+
+    Router::new()
+        .route("/signup", post(signup))
+        .route("/todo", post(create_todo))
+        .route("/target", get(target))
+        .route("/team", get(team))
+}
+
+fn post_form(uri: &str, form: &'static str) -> Request<Body> {
+    Request::post(uri)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(Body::from(form))
+        .unwrap()
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, HeaderMap, String) {
+    let response = app.oneshot(request).await.unwrap();
+    let (parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap();
+    (
+        parts.status,
+        parts.headers,
+        String::from_utf8(body.to_vec()).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn forms_deserialize_into_the_handlers_type() {
+    let (status, _, body) = send(app(), post_form("/signup", "name=Ferris&age=8")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Ferris is 8");
+}
+
+#[tokio::test]
+async fn malformed_forms_are_rejected_with_the_field() {
+    let (status, _, body) = send(app(), post_form("/signup", "name=Ferris&age=eight")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body.starts_with("invalid form field `age`"), "{body}");
+
+    let (status, _, body) = send(app(), post_form("/signup", "age=8")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body.starts_with("invalid form field `name`"), "{body}");
+}
+
+#[tokio::test]
+async fn valid_forms_reach_the_handler() {
+    let (status, _, body) = send(app(), post_form("/todo", "description=Water+plants")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Created Water plants");
+}
+
+#[tokio::test]
+async fn invalid_forms_are_re_rendered_with_their_errors() {
+    let (status, headers, body) = send(app(), post_form("/todo", "description=+")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(headers.get("HX-Retarget").is_none());
+    assert!(body.contains("<form id=\"todo-form\""), "{body}");
+    assert!(body.contains("Please describe your todo"), "{body}");
+}
+
+#[tokio::test]
+async fn invalid_htmx_forms_replace_the_form() {
+    let mut request = post_form("/todo", "description=+");
+    request
+        .headers_mut()
+        .insert("HX-Request", "true".parse().unwrap());
+    request
+        .headers_mut()
+        .insert("HX-Trigger", "todo-form".parse().unwrap());
+
+    let (status, headers, body) = send(app(), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["HX-Retarget"], "#todo-form");
+    assert_eq!(headers["HX-Reswap"], "outerHTML");
+    assert!(body.contains("Please describe your todo"), "{body}");
+}
+
+#[tokio::test]
+async fn htmx_headers_round_trip() {
+    let request = Request::get("/target")
+        .header("HX-Request", "true")
+        .header("HX-Target", "todo-list")
+        .header("HX-Trigger", "todo-form")
+        .body(Body::empty())
+        .unwrap();
+
+    let (status, headers, body) = send(app(), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["HX-Redirect"], "/login");
+    assert_eq!(headers["HX-Trigger"], "saved");
+    assert_eq!(body, r#"Some("todo-list") from Some("todo-form")"#);
+}
+
+// Tokio's test runtime is single-threaded, which components can't hold up.
+#[tokio::test]
+async fn components_render_on_a_current_thread_runtime() {
+    let request = Request::get("/team").body(Body::empty()).unwrap();
+    let (status, _, body) = send(app(), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        body.contains(r#"<span class="badge">CORE</span>"#),
+        "{body}"
+    );
+}
+
+async fn dashboard() -> &'static str {
+    "From the handler"
+}
+
+async fn team_member(template: Template) -> String {
+    format!(
+        "{} of {}",
+        template.params["member"], template.params["team"]
+    )
+}
+
+fn template_routes() -> Router {
+    app()
+        .route("/dashboard", get(dashboard))
+        .route("/teams/:team/:member", get(team_member))
+        .with_template_routes()
+}
+
+fn get_page(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn templates_without_a_handler_are_routed() {
+    let (status, headers, body) = send(template_routes(), get_page("/about")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["Content-Type"], "text/html; charset=utf-8");
+    assert!(body.contains("About us</h1>"), "{body}");
+
+    let (status, _, body) = send(template_routes(), get_page("/dashboard")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "From the handler");
+}
+
+#[tokio::test]
+async fn partials_and_missing_templates_are_not_found() {
+    let (status, _, _) = send(template_routes(), get_page("/_layout")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = send(template_routes(), get_page("/missing")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+#[should_panic(expected = "both have a fallback")]
+fn template_routes_do_not_replace_the_fallback() {
+    let _ = app()
+        .fallback(|| async { "Not here" })
+        .with_template_routes();
+}
+
+#[tokio::test]
+async fn templates_outside_of_a_route_are_not_found() {
+    let app = app().fallback(|template: Template| async move { template.render(context!()) });
+    let (status, _, _) = send(app, get_page("/about")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn route_params_are_passed_to_templates() {
+    let (status, _, body) = send(template_routes(), get_page("/users/42")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("User 42</p>"), "{body}");
+
+    let (status, _, body) = send(template_routes(), get_page("/teams/core/ferris")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "ferris of core");
+}
+
+#[cfg(feature = "bundle-js")]
+#[tokio::test]
+async fn scripts_that_fail_to_bundle_are_served_with_their_error() {
+    let app = app().with_bundled_js("/broken.js", "tests/scripts/broken.ts");
+    let (status, _, body) = send(app, get_page("/broken.js")).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(body.contains("broken.ts"), "{body}");
+}
+
+#[tokio::test]
+async fn line_breaks_in_events_stay_within_their_frame() {
+    let app = app().with_event_stream("/line-breaks");
+    let response = app.oneshot(get_page("/line-breaks")).await.unwrap();
+    assert_eq!(response.headers()["Content-Type"], "text/event-stream");
+
+    hyro::publish(
+        "/line-breaks",
+        "new\r\ntodo",
+        "greeting",
+        context!(name => "a\r\nb\rc"),
+    )
+    .unwrap();
+
+    let mut body = response.into_body();
+    let frame = hyper::body::HttpBody::data(&mut body)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(&frame).unwrap(),
+        "event:newtodo\ndata:<p>Hello, a\ndata:b\ndata:c!</p>\n\n"
+    );
+}
@@ -0,0 +1,3 @@
+import { b } from "./cycle_b";
+
+export const a = () => "a" + b();
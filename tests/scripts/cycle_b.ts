@@ -0,0 +1,4 @@
+import { a } from "./cycle_a";
+
+export const b = () => "b";
+export const loop = () => a();
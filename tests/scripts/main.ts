@@ -0,0 +1,10 @@
+import greet, { double } from "./math";
+
+interface Greeting {
+    name: string;
+    times: number;
+}
+
+export function welcome({ name, times }: Greeting): string {
+    return greet(name).repeat(double(times));
+}
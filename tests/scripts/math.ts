@@ -0,0 +1,5 @@
+export default function (name: string): string {
+    return `hello ${name}`;
+}
+
+export const double = (n: number): number => n * 2;
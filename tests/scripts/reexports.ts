@@ -0,0 +1,3 @@
+export { double as twice } from "./math";
+export * from "./shapes";
+export * as geometry from "./shapes";
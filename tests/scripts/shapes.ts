@@ -0,0 +1,2 @@
+export const sides = 4;
+export const area = (side: number) => side * side;